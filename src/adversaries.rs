@@ -0,0 +1,115 @@
+use crate::{Adversary, Distribution, Download, Peer, REQUEST_TIMEOUT};
+use std::cmp;
+use std::str::FromStr;
+
+impl FromStr for Adversary {
+    type Err = String;
+
+    /// Parses `honest`, `withholding`, `sybil:ENTITY`, `liar:FACTOR`, or
+    /// `strategic`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            ["honest"] => Ok(Adversary::Honest),
+            ["withholding"] => Ok(Adversary::Withholding),
+            ["sybil", entity] => entity
+                .parse()
+                .map(Adversary::Sybil)
+                .map_err(|_| format!("Invalid sybil entity {entity}")),
+            ["liar", factor] => factor
+                .parse()
+                .ok()
+                .filter(|f| *f > 0.0)
+                .map(Adversary::CapacityLiar)
+                .ok_or_else(|| format!("Invalid capacity factor {factor}")),
+            ["strategic"] => Ok(Adversary::Strategic),
+            _ => Err(format!("Invalid adversary {s}")),
+        }
+    }
+}
+
+impl Peer {
+    /// Returns whether the peer is neither malicious nor another kind of
+    /// adversary.
+    pub fn is_honest(&self) -> bool {
+        self.adversary == Adversary::Honest && !self.is_malicious
+    }
+
+    /// Returns whether the peer uploads to a target, which a sybil only
+    /// does if the target is controlled by the same entity.
+    pub(crate) fn uploads_to_entity(&self, target_peer: usize) -> bool {
+        self.sybil_peers
+            .as_ref()
+            .is_none_or(|peers| peers.binary_search(&target_peer).is_ok())
+    }
+
+    /// The most that a strategic peer uploads to a target per round while
+    /// it is downloading with choking. A target that gave nothing in the
+    /// previous rechoke period gets an equal share of the upload speed
+    /// among the unchoked peers, so that it can start reciprocating.
+    pub(crate) fn reciprocated_rate(&self, target_peer: usize) -> Option<usize> {
+        let unchoked_peers = self.unchoked_peers.as_ref()?;
+        if self.adversary != Adversary::Strategic || self.completion_round.is_some() {
+            return None;
+        }
+        let equal_share = cmp::max(self.upload_speed / (unchoked_peers.len() + 1), 1);
+        let rate = self.reciprocated_rates.get(&target_peer).copied();
+        Some(rate.unwrap_or(equal_share))
+    }
+}
+
+impl Distribution {
+    /// Gives the peers their adversary behaviours, and the sybils the
+    /// other peers of their entity.
+    pub(crate) fn set_adversaries(peers: &mut [Peer], adversaries: &[Adversary]) {
+        for (peer, adversary) in peers.iter_mut().zip(adversaries) {
+            peer.adversary = *adversary;
+            if let Adversary::Sybil(entity) = adversary {
+                let sybil_peers = adversaries
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, a)| **a == Adversary::Sybil(*entity))
+                    .map(|(i, _)| i)
+                    .collect();
+                peer.sybil_peers = Some(sybil_peers);
+            }
+        }
+    }
+
+    /// Returns whether a download is from a withholding peer, which accepts
+    /// the request but never sends any data.
+    pub(crate) fn is_withheld(&self, download: &Download) -> bool {
+        self.peers[download.source_peer].adversary == Adversary::Withholding
+    }
+
+    /// Gives up the withheld downloads of a peer that have timed out by
+    /// the given round.
+    pub(crate) fn withdraw_timed_out_requests(&mut self, peer_index: usize, round_number: usize) {
+        let timed_out_requests: Vec<Download> = self.peers[peer_index]
+            .current_downloads
+            .iter()
+            .filter(|d| self.is_withheld(d) && round_number >= d.request_round + REQUEST_TIMEOUT)
+            .copied()
+            .collect();
+        for download in &timed_out_requests {
+            self.time_out_request(download);
+        }
+    }
+
+    /// Gives up a withheld download whose request has timed out, and stops
+    /// the target from asking the source again.
+    pub(crate) fn time_out_request(&mut self, download: &Download) {
+        let target = &mut self.peers[download.target_peer];
+        if !target.is_downloading_from(
+            download.chunk_number,
+            download.block_number,
+            download.source_peer,
+        ) {
+            return;
+        }
+        if !target.timed_out_sources.contains(&download.source_peer) {
+            target.timed_out_sources.push(download.source_peer);
+        }
+        self.give_up_download(download, 0);
+    }
+}
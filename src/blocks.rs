@@ -0,0 +1,109 @@
+use crate::{Chunk, Distribution, Download, Peer, RunObserver};
+use std::cmp;
+
+impl Chunk {
+    /// The size of a block of the chunk. The blocks split the chunk as
+    /// evenly as possible.
+    pub fn block_size(&self, block_number: usize) -> usize {
+        self.size * (block_number + 1) / self.number_blocks
+            - self.size * block_number / self.number_blocks
+    }
+}
+
+impl Peer {
+    /// Returns the next block of a chunk for the peer to download, which is
+    /// a block that it neither has nor is downloading. In endgame mode, a
+    /// block that the peer is already downloading is returned if there is
+    /// no other block left.
+    pub(crate) fn next_block(&self, chunk: &Chunk, in_endgame: bool) -> Option<usize> {
+        let received_blocks = self.received_blocks.get(&chunk.index);
+        let missing_blocks = (0..chunk.number_blocks)
+            .filter(|b| received_blocks.is_none_or(|blocks| blocks[*b].is_none()));
+        let mut downloading_block = None;
+        for block_number in missing_blocks {
+            if !self.is_downloading_block(chunk.index, block_number) {
+                return Some(block_number);
+            }
+            downloading_block.get_or_insert(block_number);
+        }
+        downloading_block.filter(|_| in_endgame)
+    }
+
+    /// Records that the peer has received a block of a chunk. When the
+    /// peer now has every block of the chunk, returns the source of each
+    /// block, so that the chunk can be checked.
+    pub(crate) fn receive_block(
+        &mut self,
+        chunk: &Chunk,
+        download: &Download,
+    ) -> Option<Vec<usize>> {
+        let received_blocks = self
+            .received_blocks
+            .entry(chunk.index)
+            .or_insert_with(|| vec![None; chunk.number_blocks]);
+        received_blocks[download.block_number] = Some(download.source_peer);
+        if received_blocks.iter().all(|b| b.is_some()) {
+            self.received_blocks
+                .remove(&chunk.index)
+                .map(|blocks| blocks.into_iter().flatten().collect())
+        } else {
+            None
+        }
+    }
+}
+
+impl Distribution {
+    /// Starts the download of the next block of a chunk from the source of
+    /// a block that has just been downloaded, as the target has already
+    /// requested it from the source, with continuous time. Otherwise, the
+    /// source could upload single blocks of the same chunk to many peers,
+    /// none of which would have the chunk to share.
+    pub(crate) fn request_next_block<Obs: RunObserver>(
+        &mut self,
+        finished_download: &Download,
+        round_number: usize,
+        observer: &Obs,
+    ) -> Option<Download> {
+        let chunk_number = finished_download.chunk_number;
+        let target = &self.peers[finished_download.target_peer];
+        // Once the peer has every block, the chunk is either possessed or
+        // thrown away after failing the hash check
+        if !target.received_blocks.contains_key(&chunk_number) || !target.is_active {
+            return None;
+        }
+        let chunk = &self.file.chunks[chunk_number];
+        let block_number = target.next_block(chunk, false)?;
+        let used_capacity: usize = target
+            .current_downloads
+            .iter()
+            .map(|d| d.current_size)
+            .sum();
+        let download_capacity = target.download_speed.saturating_sub(used_capacity);
+        let upload_capacity = self.peers[finished_download.source_peer]
+            .available_capacity_for_block(chunk_number, block_number, target.index);
+        let capacity = cmp::min(download_capacity, upload_capacity);
+        if capacity == 0 {
+            return None;
+        }
+        let size = target.remaining_block_size(chunk, block_number);
+        self.peers[finished_download.target_peer]
+            .partial_blocks
+            .remove(&(chunk_number, block_number));
+        observer.chunk_transfer(
+            chunk_number,
+            capacity,
+            finished_download.source_peer,
+            finished_download.target_peer,
+        );
+        let download = Download {
+            block_number,
+            size,
+            downloaded_size: capacity,
+            current_size: capacity,
+            request_round: round_number,
+            ..*finished_download
+        };
+        self.transfer(download);
+        Some(download)
+    }
+}
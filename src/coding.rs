@@ -0,0 +1,305 @@
+use crate::{Coding, Distribution, Download, Peer, RunObserver, SourceSelectionContext};
+use rand::Rng;
+
+/// The powers of the generator 2 of GF(256), with the reducing polynomial
+/// x^8 + x^4 + x^3 + x^2 + 1, repeated so that a sum of two logarithms can
+/// be looked up directly
+const EXP: [u8; 512] = exp_table();
+const LOG: [usize; 256] = log_table();
+
+const fn exp_table() -> [u8; 512] {
+    let mut table = [0; 512];
+    let mut x: usize = 1;
+    let mut i = 0;
+    while i < table.len() {
+        table[i] = x as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    table
+}
+
+const fn log_table() -> [usize; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 255 {
+        table[EXP[i] as usize] = i;
+        i += 1;
+    }
+    table
+}
+
+fn multiply(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        0
+    } else {
+        EXP[LOG[a as usize] + LOG[b as usize]]
+    }
+}
+
+fn inverse(a: u8) -> u8 {
+    EXP[255 - LOG[a as usize]]
+}
+
+/// Adds a multiple of a coefficient vector to another, which is the same
+/// as subtracting it in GF(256).
+fn add_multiple(vector: &mut [u8], factor: u8, row: &[u8]) {
+    if factor != 0 {
+        for (v, r) in vector.iter_mut().zip(row) {
+            *v ^= multiply(factor, *r);
+        }
+    }
+}
+
+fn pivot(row: &[u8]) -> Option<usize> {
+    row.iter().position(|c| *c != 0)
+}
+
+/// Eliminates the pivots of rows in echelon form, ordered by their pivots
+/// which are all 1, from a coefficient vector.
+fn reduce(rows: &[Vec<u8>], vector: &mut [u8]) {
+    for row in rows {
+        if let Some(pivot) = pivot(row) {
+            add_multiple(vector, vector[pivot], row);
+        }
+    }
+}
+
+/// Adds a coefficient vector to rows in echelon form if it is linearly
+/// independent of them, and returns whether it was.
+fn insert(rows: &mut Vec<Vec<u8>>, mut vector: Vec<u8>) -> bool {
+    reduce(rows, &mut vector);
+    let Some(new_pivot) = pivot(&vector) else {
+        return false;
+    };
+    let factor = inverse(vector[new_pivot]);
+    for c in &mut vector {
+        *c = multiply(factor, *c);
+    }
+    let index = rows.partition_point(|r| pivot(r) < Some(new_pivot));
+    rows.insert(index, vector);
+    true
+}
+
+fn is_independent(rows: &[Vec<u8>], vector: &[u8]) -> bool {
+    let mut vector = vector.to_vec();
+    reduce(rows, &mut vector);
+    pivot(&vector).is_some()
+}
+
+/// A random linear combination of rows, which is uniformly distributed
+/// over the space that they span.
+fn random_combination<R: Rng + ?Sized>(rows: &[Vec<u8>], length: usize, rng: &mut R) -> Vec<u8> {
+    let mut combination = vec![0; length];
+    for row in rows {
+        add_multiple(&mut combination, rng.gen(), row);
+    }
+    combination
+}
+
+impl Distribution {
+    /// Gives the seeds the original chunks, as unit coefficient vectors,
+    /// and the other peers no coded chunks, with network coding.
+    pub(crate) fn set_coded_bases(peers: &mut [Peer], number_seeds: usize) {
+        for (i, peer) in peers.iter_mut().enumerate() {
+            let number_chunks = peer.possessed_chunks.len();
+            let basis = if i < number_seeds {
+                (0..number_chunks)
+                    .map(|c| {
+                        let mut row = vec![0; number_chunks];
+                        row[c] = 1;
+                        row
+                    })
+                    .collect()
+            } else {
+                vec![]
+            };
+            peer.coded_basis = Some(basis);
+        }
+    }
+
+    /// The number of chunks that a peer does not need to decode the file,
+    /// which are the extra coded chunks with erasure coding.
+    pub(crate) fn redundant_chunks(&self) -> usize {
+        match self.coding {
+            Some(Coding::Erasure { coded_chunks }) => coded_chunks - self.original_chunks,
+            _ => 0,
+        }
+    }
+
+    /// Cancels the remaining downloads of a peer that has just completed,
+    /// which it no longer needs with erasure coding, and returns them.
+    pub(crate) fn cancel_unneeded_downloads(&mut self, peer_index: usize) -> Vec<Download> {
+        let mut chunks: Vec<usize> = self.peers[peer_index]
+            .current_downloads
+            .iter()
+            .map(|d| d.chunk_number)
+            .collect();
+        chunks.sort_unstable();
+        chunks.dedup();
+        chunks
+            .into_iter()
+            .flat_map(|c| self.cancel_downloads(peer_index, c, None))
+            .collect()
+    }
+
+    /// Returns whether a source has a coded chunk that is linearly
+    /// independent of the coded chunks of a peer with network coding.
+    pub(crate) fn has_innovative_chunks(&self, source: &Peer, peer: &Peer) -> bool {
+        let (Some(source_basis), Some(basis)) = (&source.coded_basis, &peer.coded_basis) else {
+            return false;
+        };
+        source_basis.iter().any(|row| is_independent(basis, row))
+    }
+
+    /// Adds a downloaded coded chunk to the coded chunks of the peer with
+    /// network coding, and returns whether it was innovative, that is
+    /// linearly independent of the coded chunks that the peer had. Without
+    /// network coding, every downloaded chunk is new to the peer.
+    pub(crate) fn receive_coded_chunk(&mut self, download: &Download) -> bool {
+        let peer = &mut self.peers[download.target_peer];
+        let Some(basis) = &mut peer.coded_basis else {
+            return true;
+        };
+        let key = (download.chunk_number, download.source_peer);
+        match peer.coded_requests.remove(&key) {
+            Some(coefficients) => insert(basis, coefficients),
+            None => false,
+        }
+    }
+
+    /// Starts downloads with network coding, where the chunks of a peer
+    /// are slots for the coded chunks that it downloads, in any order. A
+    /// source sends a random linear combination of its coded chunks, which
+    /// is only drawn once the source has been selected. If the combination
+    /// is not innovative for the peer, counting the coded chunks that it is
+    /// still downloading, the source is dropped from the candidates and
+    /// another one is selected.
+    pub(crate) fn start_coded_downloads<R: Rng, Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        mut download_capacity: usize,
+        shuffled_peers: &[usize],
+        round_number: usize,
+        rng: &mut R,
+        observer: &Obs,
+    ) -> Vec<Download> {
+        let mut started_downloads = vec![];
+        let peer = &self.peers[peer_index];
+        let mut known_rows = peer.coded_basis.clone().unwrap_or_default();
+        for download in &peer.current_downloads {
+            let key = (download.chunk_number, download.source_peer);
+            if let Some(coefficients) = peer.coded_requests.get(&key) {
+                insert(&mut known_rows, coefficients.clone());
+            }
+        }
+        let missing_chunks: Vec<usize> = (0..self.file.chunks.len())
+            .filter(|c| !peer.possessed_chunks[*c] && !peer.is_downloading_block(*c, 0))
+            .collect();
+        let mut candidate_sources = shuffled_peers.to_vec();
+        for chunk_number in missing_chunks {
+            let selected_source = loop {
+                let context = SourceSelectionContext {
+                    chunk_number,
+                    block_number: 0,
+                    target_peer: peer_index,
+                    peers: &self.peers,
+                    number_seeds: self.number_seeds,
+                    shuffled_peers: &candidate_sources,
+                };
+                let Some(source_peer) = self.peers[peer_index]
+                    .source_selector
+                    .select_source(&context, rng)
+                else {
+                    break None;
+                };
+                let source_basis = self.peers[source_peer]
+                    .coded_basis
+                    .as_deref()
+                    .unwrap_or_default();
+                let combination = random_combination(source_basis, self.file.chunks.len(), rng);
+                if is_independent(&known_rows, &combination) {
+                    break Some((source_peer, combination));
+                }
+                candidate_sources.retain(|p| *p != source_peer);
+            };
+            let Some((source_peer, coefficients)) = selected_source else {
+                break;
+            };
+            let Some(download) = self.start_download(
+                chunk_number,
+                0,
+                source_peer,
+                peer_index,
+                download_capacity,
+                round_number,
+                observer,
+            ) else {
+                break;
+            };
+            insert(&mut known_rows, coefficients.clone());
+            self.peers[peer_index]
+                .coded_requests
+                .insert((chunk_number, source_peer), coefficients);
+            download_capacity -= download.current_size;
+            started_downloads.push(download);
+            if download_capacity == 0 {
+                break;
+            }
+        }
+        started_downloads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(EXP[LOG[a as usize]], a);
+        }
+        assert_eq!(EXP[255], 1);
+    }
+
+    #[test]
+    fn inverse_times_element_is_one() {
+        for a in 1..=255u8 {
+            assert_eq!(multiply(inverse(a), a), 1);
+        }
+    }
+
+    #[test]
+    fn insert_keeps_only_independent_vectors() {
+        let mut rows = vec![];
+        assert!(insert(&mut rows, vec![1, 2, 0, 0]));
+        assert!(!insert(&mut rows, vec![2, multiply(2, 2), 0, 0]));
+        assert!(insert(&mut rows, vec![0, 0, 3, 0]));
+        assert!(!insert(&mut rows, vec![1, 2, 3, 0]));
+        assert!(!insert(&mut rows, vec![0, 0, 0, 0]));
+        assert_eq!(rows.len(), 2);
+        assert!(insert(&mut rows, vec![5, 0, 0, 7]));
+        assert_eq!(rows.len(), 3);
+        assert!(is_independent(&rows, &[0, 1, 0, 0]));
+        assert!(insert(&mut rows, vec![0, 1, 0, 0]));
+        assert_eq!(rows.len(), 4);
+        assert!(!is_independent(&rows, &[9, 8, 7, 6]));
+    }
+
+    #[test]
+    fn reduced_rows_have_unit_pivots_in_order() {
+        let mut rows = vec![];
+        for vector in [vec![0, 4, 1], vec![3, 1, 1], vec![0, 0, 9]] {
+            insert(&mut rows, vector);
+        }
+        let pivots: Vec<Option<usize>> = rows.iter().map(|r| pivot(r)).collect();
+        assert_eq!(pivots, vec![Some(0), Some(1), Some(2)]);
+        for row in &rows {
+            assert_eq!(row[pivot(row).unwrap()], 1);
+        }
+    }
+}
@@ -0,0 +1,688 @@
+use crate::{Distribution, Download, Round, RunObserver, Selfishness, REQUEST_TIMEOUT};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use std::cmp::{self, Ordering};
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+enum EventKind {
+    Join(usize),
+    Abort(usize),
+    SeedingCheck(usize),
+    Rechoke(usize),
+    TrackerAnnounce(usize),
+    ChunkGenerated(usize),
+    ChunkExpired(usize),
+    Announce {
+        peer: usize,
+        chunk_number: usize,
+    },
+    /// A request to a withholding peer times out
+    RequestTimeout(Download),
+    TransferCompleted {
+        target_peer: usize,
+        chunk_number: usize,
+        block_number: usize,
+        source_peer: usize,
+        transfer_id: usize,
+    },
+}
+
+#[derive(Debug)]
+struct Event {
+    time: f64,
+    sequence: usize,
+    kind: EventKind,
+}
+
+/// A queue of events ordered by time, with events scheduled for the same
+/// time handled in the order they were scheduled.
+#[derive(Debug, Default)]
+struct EventQueue {
+    events: BinaryHeap<Event>,
+    next_sequence: usize,
+}
+
+/// The progress of a chunk transfer, which is updated whenever the rate of
+/// the transfer changes. The transfer is finished when the completion event
+/// with the current `id` is handled; events with earlier ids are stale.
+#[derive(Debug)]
+struct Transfer {
+    id: usize,
+    remaining_size: f64,
+    updated_time: f64,
+}
+
+#[derive(Debug, Default)]
+struct Transfers {
+    /// The transfers by target peer, chunk, block, and source peer
+    active: HashMap<(usize, usize, usize, usize), Transfer>,
+    next_id: usize,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, so the earliest event is the greatest
+        other
+            .time
+            .total_cmp(&self.time)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl EventQueue {
+    fn push(&mut self, time: f64, kind: EventKind) {
+        self.events.push(Event {
+            time,
+            sequence: self.next_sequence,
+            kind,
+        });
+        self.next_sequence += 1;
+    }
+
+    fn pop(&mut self) -> Option<Event> {
+        self.events.pop()
+    }
+
+    fn next_time(&self) -> Option<f64> {
+        self.events.peek().map(|e| e.time)
+    }
+
+    fn has_events(&self) -> bool {
+        !self.events.is_empty()
+    }
+}
+
+impl Transfers {
+    /// Starts a transfer that begins to make progress at `start_time`.
+    fn start(&mut self, queue: &mut EventQueue, download: &Download, start_time: f64) {
+        let transfer = Transfer {
+            id: self.next_id,
+            remaining_size: download.size as f64,
+            updated_time: start_time,
+        };
+        self.active.insert(Self::key(download), transfer);
+        self.schedule(queue, download);
+    }
+
+    /// Changes the rate of a transfer from the rate in `previous` to the
+    /// rate in `download`, and reschedules its completion.
+    fn update_rate(
+        &mut self,
+        queue: &mut EventQueue,
+        previous: &Download,
+        download: &Download,
+        time: f64,
+    ) {
+        let Some(transfer) = self.active.get_mut(&Self::key(download)) else {
+            return;
+        };
+        if time > transfer.updated_time {
+            let progress = (time - transfer.updated_time) * previous.current_size as f64;
+            transfer.remaining_size = (transfer.remaining_size - progress).max(0.0);
+            transfer.updated_time = time;
+        }
+        transfer.id = self.next_id;
+        self.schedule(queue, download);
+    }
+
+    fn schedule(&mut self, queue: &mut EventQueue, download: &Download) {
+        let transfer = &self.active[&Self::key(download)];
+        queue.push(
+            transfer.updated_time + transfer.remaining_size / download.current_size as f64,
+            EventKind::TransferCompleted {
+                target_peer: download.target_peer,
+                chunk_number: download.chunk_number,
+                block_number: download.block_number,
+                source_peer: download.source_peer,
+                transfer_id: self.next_id,
+            },
+        );
+        self.next_id += 1;
+    }
+
+    /// Removes the transfer if `transfer_id` is its current id.
+    fn finish(&mut self, key: (usize, usize, usize, usize), transfer_id: usize) -> bool {
+        if self.active.get(&key).is_some_and(|t| t.id == transfer_id) {
+            self.active.remove(&key);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes a transfer before it finishes, and returns the amount of
+    /// data it had transferred by `time`.
+    fn cancel(&mut self, download: &Download, time: f64) -> f64 {
+        let Some(transfer) = self.active.remove(&Self::key(download)) else {
+            return 0.0;
+        };
+        let progress = (time - transfer.updated_time).max(0.0) * download.current_size as f64;
+        let size = download.size as f64;
+        (size - transfer.remaining_size + progress).min(size)
+    }
+
+    fn key(download: &Download) -> (usize, usize, usize, usize) {
+        (
+            download.target_peer,
+            download.chunk_number,
+            download.block_number,
+            download.source_peer,
+        )
+    }
+
+    fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+}
+
+impl Distribution {
+    /// Runs the distribution as a discrete-event simulation.
+    ///
+    /// A chunk transfer gets the capacity that is available when it starts,
+    /// and it starts to make progress after `latency`. When a transfer
+    /// finishes, the other transfers of its source and target may speed up
+    /// to use the freed capacity. Rounds are derived from the simulated
+    /// time, so that round `n` covers the time interval `(n - 1, n]`.
+    pub(crate) fn run_events<Obs: RunObserver>(
+        &mut self,
+        latency: f64,
+        rng: &mut ChaCha8Rng,
+        observer: &Obs,
+    ) -> Vec<Round> {
+        let number_peers = self.peers.len();
+        let mut queue = EventQueue::default();
+        for peer in &self.peers[self.number_seeds..] {
+            if peer.join_time > 0.0 {
+                queue.push(peer.join_time, EventKind::Join(peer.index));
+            }
+        }
+        let abort_probability = self.departures.abort_probability;
+        if abort_probability > 0.0 {
+            // The per-round abort probability as a constant hazard rate
+            let abort_rate = -(1.0 - abort_probability).ln();
+            for peer in &self.peers[self.number_seeds..] {
+                let delay = -(1.0 - rng.gen::<f64>()).ln() / abort_rate;
+                queue.push(peer.join_time + delay, EventKind::Abort(peer.index));
+            }
+        }
+        if self.choking.is_some() {
+            queue.push(0.0, EventKind::Rechoke(0));
+        }
+        if let Some(live) = self.live {
+            for chunk_number in 0..self.file.chunks.len() {
+                queue.push(
+                    live.generation_time(chunk_number),
+                    EventKind::ChunkGenerated(chunk_number),
+                );
+                queue.push(
+                    live.expiry_time(chunk_number),
+                    EventKind::ChunkExpired(chunk_number),
+                );
+            }
+        }
+        if self.tracker.is_some() {
+            for peer in &self.peers {
+                if peer.is_active {
+                    queue.push(0.0, EventKind::TrackerAnnounce(peer.index));
+                }
+            }
+        }
+        let mut transfers = Transfers::default();
+        let mut rounds = vec![];
+        let mut current_round = self.initial_round();
+        rounds.push(current_round.clone());
+        let mut shuffled_peers: Vec<usize> = (0..number_peers).collect();
+        let mut temporary_chunks: Vec<usize> = (0..self.file.chunks.len()).collect();
+        let mut aborted_peers = 0;
+        let mut time = 0.0;
+        let mut start_time = Instant::now();
+        observer.round_start(rounds.len());
+        self.advance_playback(rounds.len());
+        loop {
+            self.abort_failed_transfers(
+                time,
+                &mut transfers,
+                &mut queue,
+                &mut current_round,
+                observer,
+            );
+            shuffled_peers[0..self.number_seeds].shuffle(rng);
+            shuffled_peers[self.number_seeds..].shuffle(rng);
+            for peer_index in self.number_seeds..number_peers {
+                let peer_index = shuffled_peers[peer_index];
+                let peer = &self.peers[peer_index];
+                if peer.completion_round.is_some() || !peer.is_active {
+                    continue;
+                }
+                let used_capacity: usize =
+                    peer.current_downloads.iter().map(|d| d.current_size).sum();
+                let download_capacity = peer.download_speed.saturating_sub(used_capacity);
+                if download_capacity == 0 {
+                    continue;
+                }
+                let started_downloads = self.start_downloads(
+                    peer_index,
+                    download_capacity,
+                    &mut temporary_chunks,
+                    &shuffled_peers,
+                    rounds.len(),
+                    rng,
+                    observer,
+                );
+                current_round.exchanged_chunks += started_downloads.len();
+                for download in started_downloads {
+                    self.record_swarm_exchange(download.chunk_number);
+                    if self.is_withheld(&download) {
+                        queue.push(
+                            time + REQUEST_TIMEOUT as f64,
+                            EventKind::RequestTimeout(download),
+                        );
+                    } else {
+                        transfers.start(&mut queue, &download, time + latency);
+                    }
+                }
+            }
+            if current_round.completed_peers + aborted_peers >= number_peers {
+                break;
+            }
+            let Some(next_time) = queue.next_time() else {
+                break;
+            };
+            while next_time > rounds.len() as f64 {
+                current_round.rarity_drift = self.take_rarity_drift();
+                current_round.swarms = self.take_swarm_rounds();
+                current_round.execution_time = start_time.elapsed();
+                observer.round_end(rounds.len(), &current_round);
+                rounds.push(current_round.clone());
+                current_round = Round::new(&current_round);
+                start_time = Instant::now();
+                observer.round_start(rounds.len());
+                self.advance_playback(rounds.len());
+            }
+            time = next_time;
+            let round_number = rounds.len();
+            while queue.next_time() == Some(time) {
+                let Some(event) = queue.pop() else {
+                    break;
+                };
+                match event.kind {
+                    EventKind::Join(peer_index) => {
+                        let peer = &mut self.peers[peer_index];
+                        if !peer.is_active && peer.departure_round.is_none() {
+                            peer.is_active = true;
+                            observer.peer_joined(peer_index);
+                            current_round.joined_peers += 1;
+                            if self.tracker.is_some() {
+                                queue.push(time, EventKind::TrackerAnnounce(peer_index));
+                            }
+                        }
+                    }
+                    EventKind::Abort(peer_index) => {
+                        let peer = &self.peers[peer_index];
+                        if peer.is_active && peer.completion_round.is_none() {
+                            self.leave(
+                                peer_index,
+                                round_number,
+                                time,
+                                &mut transfers,
+                                &mut queue,
+                                &mut current_round,
+                                observer,
+                            );
+                            aborted_peers += 1;
+                        }
+                    }
+                    EventKind::SeedingCheck(peer_index) => {
+                        if self.has_finished_seeding(peer_index, round_number) {
+                            self.leave(
+                                peer_index,
+                                round_number,
+                                time,
+                                &mut transfers,
+                                &mut queue,
+                                &mut current_round,
+                                observer,
+                            );
+                        }
+                    }
+                    EventKind::Rechoke(round_index) => {
+                        let Some(choking) = self.choking else {
+                            continue;
+                        };
+                        self.update_choking(&choking, round_index, rng);
+                        // Peers that everyone has choked can only continue
+                        // after a later rechoke
+                        if !transfers.is_empty() || queue.has_events() || self.can_progress() {
+                            let next_index =
+                                |interval: usize| (round_index / interval + 1) * interval;
+                            let next_round_index = cmp::min(
+                                next_index(choking.rechoke_interval),
+                                next_index(choking.optimistic_unchoke_interval),
+                            );
+                            queue.push(
+                                time + (next_round_index - round_index) as f64,
+                                EventKind::Rechoke(next_round_index),
+                            );
+                        }
+                    }
+                    EventKind::TrackerAnnounce(peer_index) => {
+                        let Some(tracker) = self.tracker else {
+                            continue;
+                        };
+                        if !self.peers[peer_index].is_active {
+                            continue;
+                        }
+                        self.announce_to_tracker(peer_index, round_number, rng, observer);
+                        if !transfers.is_empty() || self.can_progress() {
+                            queue.push(
+                                time + tracker.announce_interval as f64,
+                                EventKind::TrackerAnnounce(peer_index),
+                            );
+                        }
+                    }
+                    EventKind::ChunkGenerated(chunk_number) => {
+                        self.generate_chunk(chunk_number);
+                    }
+                    EventKind::ChunkExpired(chunk_number) => {
+                        let (cancelled_downloads, completed_peers) =
+                            self.expire_chunk(chunk_number, round_number, observer);
+                        for cancelled in &cancelled_downloads {
+                            transfers.cancel(cancelled, time);
+                            self.speed_up_transfers(
+                                cancelled.source_peer,
+                                time,
+                                &mut transfers,
+                                &mut queue,
+                            );
+                        }
+                        for peer_index in completed_peers {
+                            current_round.completed_peers += 1;
+                            if let Some(seeding_rounds) = self.seeding_period(peer_index) {
+                                queue.push(
+                                    time + seeding_rounds as f64,
+                                    EventKind::SeedingCheck(peer_index),
+                                );
+                            }
+                        }
+                    }
+                    EventKind::Announce { peer, chunk_number } => {
+                        self.announce(peer, chunk_number);
+                    }
+                    EventKind::RequestTimeout(download) => {
+                        self.time_out_request(&download);
+                    }
+                    EventKind::TransferCompleted {
+                        target_peer,
+                        chunk_number,
+                        block_number,
+                        source_peer,
+                        transfer_id,
+                    } => {
+                        let key = (target_peer, chunk_number, block_number, source_peer);
+                        if !transfers.finish(key, transfer_id) {
+                            continue;
+                        }
+                        let Some(download) = self.peers[target_peer].finish_download(
+                            chunk_number,
+                            block_number,
+                            source_peer,
+                        ) else {
+                            continue;
+                        };
+                        self.peers[source_peer].chunk_upload_finished(
+                            chunk_number,
+                            block_number,
+                            target_peer,
+                        );
+                        if source_peer < self.number_seeds {
+                            current_round.seed_uploads += 1;
+                        }
+                        let mut cancelled_downloads = self.cancel_downloads(
+                            target_peer,
+                            chunk_number,
+                            Some(download.block_number),
+                        );
+                        for cancelled in &cancelled_downloads {
+                            current_round.wasted_chunks +=
+                                transfers.cancel(cancelled, time) / self.chunk_size as f64;
+                        }
+                        self.record_transfer(&download, download.size);
+                        let chunk = &self.file.chunks[chunk_number];
+                        let has_chunk = match self.peers[target_peer]
+                            .receive_block(chunk, &download)
+                        {
+                            Some(block_sources) if !self.passes_hash_check(&block_sources, rng) => {
+                                current_round.corrupted_chunks += 1;
+                                current_round.wasted_chunks +=
+                                    chunk.size as f64 / self.chunk_size as f64;
+                                let (bans, stopped_downloads) = self.record_hash_failure(
+                                    target_peer,
+                                    chunk_number,
+                                    &block_sources,
+                                    observer,
+                                );
+                                current_round.bans += bans;
+                                for stopped in &stopped_downloads {
+                                    current_round.wasted_chunks +=
+                                        transfers.cancel(stopped, time) / self.chunk_size as f64;
+                                }
+                                cancelled_downloads.extend(stopped_downloads);
+                                false
+                            }
+                            Some(_) => self.receive_coded_chunk(&download),
+                            None => false,
+                        };
+                        if has_chunk {
+                            self.peers[target_peer].possessed_chunks[chunk_number] = true;
+                            if self.chunk_possessed(chunk_number, round_number, observer) {
+                                current_round.completed_chunks += 1;
+                            }
+                            if self.announcement_delay == 0 {
+                                self.announce(target_peer, chunk_number);
+                            } else {
+                                queue.push(
+                                    time + self.announcement_delay as f64,
+                                    EventKind::Announce {
+                                        peer: target_peer,
+                                        chunk_number,
+                                    },
+                                );
+                            }
+                            if self.check_peer_completed(target_peer, round_number, observer) {
+                                current_round.completed_peers += 1;
+                                for cancelled in self.cancel_unneeded_downloads(target_peer) {
+                                    current_round.wasted_chunks +=
+                                        transfers.cancel(&cancelled, time) / self.chunk_size as f64;
+                                    cancelled_downloads.push(cancelled);
+                                }
+                                if let Some(seeding_rounds) = self.seeding_period(target_peer) {
+                                    queue.push(
+                                        time + seeding_rounds as f64,
+                                        EventKind::SeedingCheck(target_peer),
+                                    );
+                                }
+                            }
+                        }
+                        for peer_index in [target_peer, source_peer] {
+                            if self.has_finished_seeding(peer_index, round_number) {
+                                self.leave(
+                                    peer_index,
+                                    round_number,
+                                    time,
+                                    &mut transfers,
+                                    &mut queue,
+                                    &mut current_round,
+                                    observer,
+                                );
+                            }
+                        }
+                        if let Some(next_download) =
+                            self.request_next_block(&download, round_number, observer)
+                        {
+                            current_round.exchanged_chunks += 1;
+                            self.record_swarm_exchange(chunk_number);
+                            transfers.start(&mut queue, &next_download, time);
+                        }
+                        let cancelled_sources = cancelled_downloads.iter().map(|d| d.source_peer);
+                        for peer_index in [source_peer, target_peer]
+                            .into_iter()
+                            .chain(cancelled_sources)
+                        {
+                            self.speed_up_transfers(peer_index, time, &mut transfers, &mut queue);
+                        }
+                    }
+                }
+            }
+        }
+        current_round.rarity_drift = self.take_rarity_drift();
+        current_round.swarms = self.take_swarm_rounds();
+        current_round.execution_time = start_time.elapsed();
+        observer.round_end(rounds.len(), &current_round);
+        rounds.push(current_round);
+        rounds
+    }
+
+    /// Gives the unused capacity of a peer to its ongoing transfers, as far
+    /// as the other end of each transfer has capacity left.
+    fn speed_up_transfers(
+        &mut self,
+        peer_index: usize,
+        time: f64,
+        transfers: &mut Transfers,
+        queue: &mut EventQueue,
+    ) {
+        let peer = &self.peers[peer_index];
+        let mut ongoing_transfers: Vec<(usize, usize, usize, usize)> =
+            peer.current_uploads.iter().map(Transfers::key).collect();
+        ongoing_transfers.extend(peer.current_downloads.iter().map(Transfers::key));
+        for key in ongoing_transfers {
+            let (target_peer, chunk_number, block_number, _) = key;
+            let target = &self.peers[target_peer];
+            let Some(download) = target
+                .current_downloads
+                .iter()
+                .find(|d| Transfers::key(d) == key)
+                .copied()
+            else {
+                continue;
+            };
+            if self.is_withheld(&download) {
+                continue;
+            }
+            let used_download_capacity: usize = target
+                .current_downloads
+                .iter()
+                .map(|d| d.current_size)
+                .sum();
+            let free_download_capacity =
+                target.download_speed.saturating_sub(used_download_capacity);
+            let free_upload_capacity = self.peers[download.source_peer]
+                .available_capacity_for_block(chunk_number, block_number, target_peer)
+                .saturating_sub(download.current_size);
+            let extra_capacity = cmp::min(free_download_capacity, free_upload_capacity);
+            if extra_capacity == 0 {
+                continue;
+            }
+            let mut faster_download = download;
+            faster_download.current_size += extra_capacity;
+            self.transfer(faster_download);
+            transfers.update_rate(queue, &download, &faster_download, time);
+        }
+    }
+
+    fn seeding_period(&self, peer_index: usize) -> Option<usize> {
+        match self.peers[peer_index].selfishness {
+            Selfishness::SeedForRounds(rounds) => Some(rounds),
+            Selfishness::SeedUntilRatio(_) => None,
+            _ => self.departures.seeding_rounds,
+        }
+    }
+
+    fn has_finished_seeding(&self, peer_index: usize, round_number: usize) -> bool {
+        let peer = &self.peers[peer_index];
+        peer_index >= self.number_seeds
+            && peer.is_active
+            && peer.has_finished_seeding(
+                round_number,
+                self.departures.seeding_rounds,
+                self.blocks_per_chunk,
+            )
+    }
+
+    /// Makes a peer leave. The transfers of its downloads are cancelled and
+    /// its uploads are aborted, and the other ends of them can use the freed
+    /// capacity for their other transfers.
+    #[allow(clippy::too_many_arguments)]
+    fn leave<Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        round_number: usize,
+        time: f64,
+        transfers: &mut Transfers,
+        queue: &mut EventQueue,
+        round: &mut Round,
+        observer: &Obs,
+    ) {
+        for download in self.depart(peer_index, round_number, observer) {
+            transfers.cancel(&download, time);
+            self.speed_up_transfers(download.source_peer, time, transfers, queue);
+        }
+        round.left_peers += 1;
+        self.abort_failed_transfers(time, transfers, queue, round, observer);
+    }
+
+    /// Aborts the downloads whose source has left or stopped uploading, and
+    /// gives the freed capacity of their targets to their other transfers.
+    fn abort_failed_transfers<Obs: RunObserver>(
+        &mut self,
+        time: f64,
+        transfers: &mut Transfers,
+        queue: &mut EventQueue,
+        round: &mut Round,
+        observer: &Obs,
+    ) {
+        let (aborted_downloads, dropped_size) =
+            self.abort_failed_downloads(|d| transfers.cancel(d, time).floor() as usize, observer);
+        round.aborted_downloads += aborted_downloads.len();
+        round.wasted_chunks += dropped_size as f64 / self.chunk_size as f64;
+        for download in &aborted_downloads {
+            self.speed_up_transfers(download.target_peer, time, transfers, queue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{sorted_completion_rounds, trivial_config};
+    use crate::{Distribution, EmptyRunObserver, Engine};
+
+    #[test]
+    fn events_complete_every_peer_like_rounds() {
+        let mut config = trivial_config();
+        config.set_engine(Engine::Events { latency: 0.0 });
+        let mut distribution = Distribution::new(&config);
+        let rounds = distribution.run(Some(1), EmptyRunObserver);
+        assert_eq!(rounds.len() - 1, 3);
+        assert_eq!(
+            sorted_completion_rounds(&distribution),
+            [Some(0), Some(2), Some(3)]
+        );
+    }
+}
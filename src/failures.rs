@@ -0,0 +1,82 @@
+use crate::{Chunk, Distribution, Download, PartialProgress, Peer, RunObserver};
+
+impl Peer {
+    /// The size of a block that the peer still has to download, which is
+    /// less than the block size if it kept part of the block from an
+    /// aborted download.
+    pub(crate) fn remaining_block_size(&self, chunk: &Chunk, block_number: usize) -> usize {
+        let kept_size = self.partial_blocks.get(&(chunk.index, block_number));
+        chunk.block_size(block_number) - kept_size.copied().unwrap_or(0)
+    }
+}
+
+impl Distribution {
+    /// Gives up a download after `downloaded_size` of it has been
+    /// downloaded, so that the target can request the block from another
+    /// source. With the keep policy, the target keeps what it has of the
+    /// block, including what it kept from earlier downloads of it. Returns
+    /// the amount of the download that the target threw away.
+    pub(crate) fn give_up_download(
+        &mut self,
+        download: &Download,
+        downloaded_size: usize,
+    ) -> usize {
+        let is_same_download = |d: &Download| {
+            d.chunk_number == download.chunk_number
+                && d.block_number == download.block_number
+                && d.source_peer == download.source_peer
+                && d.target_peer == download.target_peer
+        };
+        self.peers[download.source_peer]
+            .current_uploads
+            .retain(|u| !is_same_download(u));
+        let target = &mut self.peers[download.target_peer];
+        target.current_downloads.retain(|d| !is_same_download(d));
+        target
+            .coded_requests
+            .remove(&(download.chunk_number, download.source_peer));
+        if self.partial_progress == PartialProgress::Keep {
+            let chunk = &self.file.chunks[download.chunk_number];
+            let kept_size =
+                chunk.block_size(download.block_number) - download.size + downloaded_size;
+            if kept_size > 0
+                && !target.is_downloading_block(download.chunk_number, download.block_number)
+            {
+                target
+                    .partial_blocks
+                    .insert((download.chunk_number, download.block_number), kept_size);
+                return 0;
+            }
+        }
+        downloaded_size
+    }
+
+    /// Aborts the downloads whose source has left or stopped uploading,
+    /// like a selfish peer that has completed. `downloaded_size` gives the
+    /// amount that each of them has downloaded, which the target keeps or
+    /// drops according to the partial progress policy. Returns the aborted
+    /// downloads and the amount of data that their targets dropped.
+    pub(crate) fn abort_failed_downloads<Obs: RunObserver>(
+        &mut self,
+        mut downloaded_size: impl FnMut(&Download) -> usize,
+        observer: &Obs,
+    ) -> (Vec<Download>, usize) {
+        let failed_downloads: Vec<Download> = self
+            .peers
+            .iter()
+            .flat_map(|p| &p.current_downloads)
+            .filter(|d| !self.peers[d.source_peer].allows_download())
+            .copied()
+            .collect();
+        let mut dropped_size = 0;
+        for download in &failed_downloads {
+            observer.download_aborted(
+                download.chunk_number,
+                download.source_peer,
+                download.target_peer,
+            );
+            dropped_size += self.give_up_download(download, downloaded_size(download));
+        }
+        (failed_downloads, dropped_size)
+    }
+}
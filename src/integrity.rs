@@ -0,0 +1,77 @@
+use crate::{Distribution, Download, RunObserver};
+use rand::Rng;
+
+impl Distribution {
+    /// Returns whether a chunk that a peer has received every block of
+    /// passes its hash check. The check fails if any block came from a
+    /// malicious peer, and otherwise with the corruption probability.
+    pub(crate) fn passes_hash_check<R: Rng + ?Sized>(
+        &self,
+        block_sources: &[usize],
+        rng: &mut R,
+    ) -> bool {
+        let is_corrupted =
+            self.corruption_probability > 0.0 && rng.gen_bool(self.corruption_probability);
+        !is_corrupted && block_sources.iter().all(|s| !self.peers[*s].is_malicious)
+    }
+
+    /// Counts a failed hash check of a chunk against every source that
+    /// sent a block of it, as the peer cannot tell which block was bad, and
+    /// bans the sources that reach the ban threshold. Returns the number of
+    /// sources banned and the downloads that the bans stopped.
+    pub(crate) fn record_hash_failure<Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        chunk_number: usize,
+        block_sources: &[usize],
+        observer: &Obs,
+    ) -> (usize, Vec<Download>) {
+        observer.chunk_corrupted(peer_index, chunk_number);
+        let mut sources = block_sources.to_vec();
+        sources.sort_unstable();
+        sources.dedup();
+        let mut bans = 0;
+        let mut stopped_downloads = vec![];
+        for source_peer in sources {
+            let hash_failures = self.peers[peer_index]
+                .hash_failures
+                .entry(source_peer)
+                .or_default();
+            *hash_failures += 1;
+            if self.ban_threshold.is_some_and(|t| *hash_failures >= t)
+                && !self.peers[peer_index].banned_peers.contains(&source_peer)
+            {
+                stopped_downloads.extend(self.ban(peer_index, source_peer, observer));
+                bans += 1;
+            }
+        }
+        (bans, stopped_downloads)
+    }
+
+    /// Disconnects two peers after one of them banned the other, so that
+    /// they stop their transfers and no longer exchange chunks. Returns the
+    /// stopped downloads, whose data is thrown away.
+    fn ban<Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        banned_peer: usize,
+        observer: &Obs,
+    ) -> Vec<Download> {
+        observer.peer_banned(peer_index, banned_peer);
+        let mut stopped_downloads = vec![];
+        for (peer, other_peer) in [(peer_index, banned_peer), (banned_peer, peer_index)] {
+            let peer = &mut self.peers[peer];
+            peer.banned_peers.push(other_peer);
+            peer.current_downloads.retain(|d| {
+                if d.source_peer == other_peer {
+                    stopped_downloads.push(*d);
+                    false
+                } else {
+                    true
+                }
+            });
+            peer.current_uploads.retain(|u| u.target_peer != other_peer);
+        }
+        stopped_downloads
+    }
+}
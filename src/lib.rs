@@ -1,29 +1,98 @@
+mod adversaries;
+mod blocks;
+mod coding;
+mod events;
+mod failures;
+mod integrity;
+mod live;
+mod playback;
+mod super_seeding;
+mod swarms;
+mod topology;
+mod tracker;
+
 use chrono::Utc;
 use clap::ValueEnum;
 use num::integer::{gcd, lcm};
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, RngCore};
 use rand_chacha::rand_core::SeedableRng;
 use rand_chacha::ChaCha8Rng;
 use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use std::str::{self, FromStr};
 use std::time::{Duration, Instant};
 
+const MAX_EXACT_CHUNK_SIZE: usize = 1000;
+const CHUNK_RESOLUTION: usize = 1000;
+const DEFAULT_RARITY_SAMPLE_SIZE: usize = 10;
+const DEFAULT_INITIAL_RANDOM_CHUNKS: usize = 4;
+const DEFAULT_STREAMING_LOOKAHEAD: f64 = 10.0;
+/// The number of rounds after which a peer gives up a request that a
+/// withholding peer has not served
+const REQUEST_TIMEOUT: usize = 2;
+
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Selfishness {
     #[default]
     Altruistic,
     Selfish,
     Freerider,
+    /// Leaves after seeding for the given number of rounds after completion
+    SeedForRounds(usize),
+    /// Leaves after completion once it has uploaded the given multiple of
+    /// the number of chunks
+    SeedUntilRatio(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Adversary {
+    #[default]
+    Honest,
+    /// Announces its chunks and accepts requests for them, but never
+    /// uploads, so that the requesting peers wait until their requests time
+    /// out
+    Withholding,
+    /// One of the peers controlled by the entity with the given number,
+    /// which upload only to peers controlled by the same entity
+    Sybil(usize),
+    /// Claims the given multiple of its available upload capacity to the
+    /// peers that select sources by capacity
+    CapacityLiar(f64),
+    /// Uploads like BitTyrant while downloading with choking: unchokes the
+    /// peers that give the most in return for what it sends them, and sends
+    /// each of them only as much as it got from them in the previous
+    /// rechoke period
+    Strategic,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
 pub enum Strategy {
     #[default]
     RarestFirst,
+    /// Rarest first based only on the chunks of a sample of known peers
+    LocalRarestFirst,
+    /// Uniform until the peer has a few chunks, then rarest first
+    RandomFirst,
+    /// In order of playback deadlines for chunks needed soon, then rarest first
+    Streaming,
     MostCommonFirst,
     Uniform,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum SourceStrategy {
+    #[default]
+    NonSeedsFirst,
+    SeedsFirst,
+    Fastest,
+    LeastLoaded,
+    Random,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum Speed {
     #[default]
@@ -32,29 +101,219 @@ pub enum Speed {
     Slow,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct TierSpeed {
+    upload: usize,
+    download: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpeedTiers {
+    fast: TierSpeed,
+    medium: TierSpeed,
+    slow: TierSpeed,
+}
+
+#[derive(Debug, Clone)]
+pub enum SpeedDistribution {
+    /// Speeds drawn uniformly from the range `min..=max`
+    Uniform { min: f64, max: f64 },
+    /// Speeds whose natural logarithm is normally distributed with mean
+    /// `mu` and standard deviation `sigma`
+    LogNormal { mu: f64, sigma: f64 },
+    /// Speeds drawn uniformly from a list of measured speeds
+    Empirical(Vec<f64>),
+}
+
 #[derive(Debug)]
 pub struct PeerConfig {
     selfishness: Selfishness,
-    strategy: Strategy,
+    chunk_selector: Rc<dyn ChunkSelector>,
+    source_selector: Rc<dyn SourceSelector>,
     speed: Speed,
+    adversary: Adversary,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+pub struct ChokingConfig {
+    upload_slots: usize,
+    rechoke_interval: usize,
+    optimistic_unchoke_interval: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrackerConfig {
+    peer_list_size: usize,
+    announce_interval: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum ArrivalSchedule {
+    /// All peers are present from the beginning.
+    #[default]
+    FlashCrowd,
+    /// Peers arrive one by one with exponentially distributed intervals,
+    /// `rate` being the average number of arrivals per round.
+    Poisson { rate: f64 },
+    /// The join rounds of the non-seed peers in order. Peers without a
+    /// join round are present from the beginning.
+    Explicit(Vec<usize>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LiveConfig {
+    chunk_interval: usize,
+    window: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlaybackConfig {
+    playback_rate: f64,
+    startup_delay: usize,
+}
+
+/// The playback state of a peer that plays the file while downloading it.
+#[derive(Debug, Clone, Copy)]
+pub struct Playback {
+    config: PlaybackConfig,
+    /// The time at which playback started, if it has started
+    pub start_time: Option<f64>,
+    /// The time from joining to the start of playback, if it has started
+    pub startup_latency: Option<f64>,
+    /// The number of chunks played, including the played part of the
+    /// current chunk
+    pub position: f64,
+    pub stall_count: usize,
+    /// The total time, in rounds, that playback has been stalled
+    pub stall_duration: f64,
+    is_stalled: bool,
+}
+
+/// A file that is distributed in its own swarm alongside other files.
+#[derive(Debug, Clone)]
+pub struct SwarmConfig {
+    number_chunks: usize,
+    peers: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DepartureConfig {
+    seeding_rounds: Option<usize>,
+    abort_probability: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum Topology {
+    /// Every peer is a neighbour of every other peer.
+    #[default]
+    Complete,
+    /// A random graph where every peer has `degree` neighbours.
+    RandomRegular { degree: usize },
+    /// An Erdős–Rényi random graph where each pair of peers is connected
+    /// with `probability`.
+    ErdosRenyi { probability: f64 },
+    /// A Watts–Strogatz small-world graph, a ring where every peer is
+    /// connected to its `degree` nearest peers and each connection is
+    /// rewired to a random peer with `rewiring_probability`.
+    SmallWorld {
+        degree: usize,
+        rewiring_probability: f64,
+    },
+    /// A Barabási–Albert scale-free graph, where peers are added one by one
+    /// and each new peer is connected to `edges_per_peer` earlier peers
+    /// chosen with probability proportional to their number of neighbours.
+    ScaleFree { edges_per_peer: usize },
+    /// The given connections between pairs of peers.
+    Edges(Vec<(usize, usize)>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Engine {
+    /// Time advances in rounds, and every transfer is quantized to whole
+    /// units per round.
+    #[default]
+    Rounds,
+    /// Time is continuous, and each chunk transfer is scheduled to finish
+    /// at a real-valued time. A transfer takes `latency` rounds in addition
+    /// to the time it takes to transfer the chunk.
+    Events { latency: f64 },
+}
+
+/// What a peer does with the part of a block that it has downloaded when
+/// the download is aborted because the source left or stopped uploading.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum PartialProgress {
+    /// Throws it away, and downloads the whole block from a new source
+    #[default]
+    Drop,
+    /// Keeps it, and downloads only the rest of the block from a new source
+    Keep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Coding {
+    /// The file is encoded into `coded_chunks` chunks, of which any
+    /// `number_chunks` distinct ones are enough to decode it. Peers upload
+    /// only the coded chunks that they have downloaded.
+    Erasure { coded_chunks: usize },
+    /// Random linear network coding over GF(256), where every upload is a
+    /// random linear combination of the coded chunks that the source has,
+    /// and any `number_chunks` linearly independent ones are enough to
+    /// decode the file.
+    Network,
+}
+
+#[derive(Debug, Clone)]
 pub struct Config {
     number_chunks: usize,
     number_peers: usize,
     number_seeds: usize,
-    chunk_size: usize,
     peer_selfishness: Vec<Selfishness>,
-    peer_strategies: Vec<Strategy>,
-    peer_speeds: Vec<usize>,
+    peer_chunk_selectors: Vec<Rc<dyn ChunkSelector>>,
+    peer_source_selectors: Vec<Rc<dyn SourceSelector>>,
+    peer_upload_speeds: Vec<f64>,
+    peer_download_speeds: Vec<f64>,
+    choking: Option<ChokingConfig>,
+    arrivals: ArrivalSchedule,
+    departures: DepartureConfig,
+    topology: Topology,
+    tracker: Option<TrackerConfig>,
+    engine: Engine,
+    announcement_delay: usize,
+    endgame_chunks: Option<usize>,
+    playback: Option<PlaybackConfig>,
+    live: Option<LiveConfig>,
+    super_seeding: bool,
+    swarms: Vec<SwarmConfig>,
+    chunk_sizes: Option<Vec<f64>>,
+    blocks_per_chunk: usize,
+    peer_malicious: Vec<bool>,
+    peer_adversaries: Vec<Adversary>,
+    corruption_probability: f64,
+    ban_threshold: Option<usize>,
+    coding: Option<Coding>,
+    partial_progress: PartialProgress,
 }
 
 #[derive(Debug)]
 pub struct Chunk {
     index: usize,
+    /// The size of the chunk in the same units as the peer speeds
+    pub size: usize,
+    /// The number of blocks that the chunk is split into, which can be
+    /// downloaded from different sources
+    pub number_blocks: usize,
     pub completion_round: Option<usize>,
+    /// The round in which a peer other than a seed first got the chunk
+    pub first_copy_round: Option<usize>,
     pub number_possessing_peers: usize,
+    /// The number of peers that have possessed the chunk, including those
+    /// that have left since, which decides when the chunk is completed
+    pub number_ever_possessing_peers: usize,
+    /// The number of peers whose possession of the chunk is known to the
+    /// other peers, which lags behind `number_possessing_peers` when
+    /// announcements are delayed.
+    pub number_announcing_peers: usize,
 }
 
 #[derive(Debug)]
@@ -62,26 +321,98 @@ pub struct File {
     pub chunks: Vec<Chunk>,
 }
 
+/// The swarm of one file when several files are distributed at once. The
+/// chunks of all files make up one combined file.
+#[derive(Debug)]
+pub struct Swarm {
+    /// The chunks of the file in the combined file
+    pub chunks: Range<usize>,
+    /// The peers that take part in the swarm, including all seeds, in
+    /// ascending order
+    pub peers: Vec<usize>,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Download {
     chunk_number: usize,
+    block_number: usize,
+    /// The size of the block, or of the whole chunk if it is not split,
+    /// less what the target kept from an aborted download of it
+    size: usize,
     source_peer: usize,
     target_peer: usize,
     downloaded_size: usize,
     current_size: usize,
+    /// The round in which the target requested the block from the source
+    request_round: usize,
 }
 
 #[derive(Debug)]
 pub struct Peer {
     index: usize,
     pub selfishness: Selfishness,
-    pub strategy: Strategy,
-    pub speed: usize,
+    pub chunk_selector: Rc<dyn ChunkSelector>,
+    pub source_selector: Rc<dyn SourceSelector>,
+    pub upload_speed: usize,
+    pub download_speed: usize,
     pub completion_round: Option<usize>,
     pub possessed_chunks: Vec<bool>,
+    /// The chunks that the other peers know this peer to possess.
+    pub announced_chunks: Vec<bool>,
+    /// The number of finished uploads, which are uploads of blocks when
+    /// chunks are split into blocks
     pub number_uploads: usize,
+    pub join_round: usize,
+    join_time: f64,
+    pub departure_round: Option<usize>,
+    pub playback: Option<Playback>,
+    /// The number of chunks that fell out of the live window before the
+    /// peer downloaded them
+    pub missed_chunks: usize,
+    /// The round in which the peer completed the file of each swarm that
+    /// it takes part in
+    pub swarm_completion_rounds: Vec<Option<usize>>,
+    /// Whether the peer uploads only bad data, which fails the hash check
+    pub is_malicious: bool,
+    pub adversary: Adversary,
+    is_active: bool,
+    neighbours: Option<Vec<usize>>,
+    last_announce_round: Option<usize>,
     current_uploads: Vec<Download>,
-    current_download: Option<Download>,
+    current_downloads: Vec<Download>,
+    unchoked_peers: Option<Vec<usize>>,
+    optimistic_unchoke: Option<usize>,
+    received_from: HashMap<usize, usize>,
+    sent_to: HashMap<usize, usize>,
+    /// The sources of the blocks received so far of the chunks that the
+    /// peer has started but not finished downloading
+    received_blocks: HashMap<usize, Vec<Option<usize>>>,
+    /// The chunk that a super-seeding seed offers to each peer
+    super_seeding_offers: Option<HashMap<usize, usize>>,
+    /// The number of failed hash checks of chunks that each source sent
+    /// blocks of
+    hash_failures: HashMap<usize, usize>,
+    /// The peers that this peer no longer exchanges chunks with, because
+    /// one of them banned the other
+    banned_peers: Vec<usize>,
+    /// The sources whose requests from this peer timed out, which it no
+    /// longer asks for chunks
+    timed_out_sources: Vec<usize>,
+    /// The peers controlled by the same entity, if the peer is a sybil,
+    /// which are the only peers it uploads to
+    sybil_peers: Option<Vec<usize>>,
+    /// The upload rate that a strategic peer gives each peer, which is what
+    /// it got from the peer per round in the previous rechoke period
+    reciprocated_rates: HashMap<usize, usize>,
+    /// The coefficient vectors of the coded chunks that the peer has with
+    /// network coding, in echelon form
+    coded_basis: Option<Vec<Vec<u8>>>,
+    /// The coefficient vectors of the coded chunks that the peer is
+    /// downloading with network coding, by chunk and source
+    coded_requests: HashMap<(usize, usize), Vec<u8>>,
+    /// The amount that the peer kept of each block, by chunk and block,
+    /// whose download was aborted and not yet resumed
+    partial_blocks: HashMap<(usize, usize), usize>,
 }
 
 #[derive(Debug)]
@@ -89,7 +420,39 @@ pub struct Distribution {
     pub file: File,
     pub peers: Vec<Peer>,
     pub number_seeds: usize,
+    /// The swarms of the files, if several files are distributed at once
+    pub swarms: Vec<Swarm>,
     chunk_size: usize,
+    /// Whether the chunks have their own sizes, in which case the ongoing
+    /// downloads of all peers continue before any new download starts
+    has_chunk_sizes: bool,
+    blocks_per_chunk: usize,
+    corruption_probability: f64,
+    ban_threshold: Option<usize>,
+    coding: Option<Coding>,
+    /// The number of chunks that are needed to decode the file, which is
+    /// less than the number of chunks with erasure coding
+    original_chunks: usize,
+    partial_progress: PartialProgress,
+    choking: Option<ChokingConfig>,
+    arrivals: ArrivalSchedule,
+    departures: DepartureConfig,
+    topology: Topology,
+    tracker: Option<TrackerConfig>,
+    engine: Engine,
+    announcement_delay: usize,
+    endgame_chunks: Option<usize>,
+    live: Option<LiveConfig>,
+    /// The number of chunks generated so far in live mode
+    generated_chunks: usize,
+    /// The number of chunks that have fallen out of the live window, which
+    /// are always the first chunks
+    expired_chunks: usize,
+    /// Downloaded chunks that are not yet announced, as the round in which
+    /// the announcement is due, the peer, and the chunk.
+    pending_announcements: VecDeque<(usize, usize, usize)>,
+    rarity_drifts: Vec<f64>,
+    swarm_rounds: Vec<SwarmRound>,
 }
 
 #[derive(Debug, Clone)]
@@ -97,9 +460,123 @@ pub struct Round {
     pub completed_peers: usize,
     pub completed_chunks: usize,
     pub exchanged_chunks: usize,
+    pub joined_peers: usize,
+    pub left_peers: usize,
+    /// How much the chunk selection order of peers differs from the order
+    /// by the actual rarity of the chunks, averaged over chunk selections.
+    pub rarity_drift: f64,
+    /// The amount of data, in chunks, that was transferred in endgame mode
+    /// by downloads that were cancelled because another download of the
+    /// same chunk finished first, or with erasure coding by downloads that
+    /// were cancelled because the peer could already decode the file. It
+    /// also includes the chunks that failed the hash check, the data of
+    /// downloads stopped by bans, and the data that targets dropped from
+    /// aborted downloads.
+    pub wasted_chunks: f64,
+    /// The number of chunk uploads by the initial seeds finished in the round
+    pub seed_uploads: usize,
+    /// The number of downloaded chunks that failed the hash check and were
+    /// thrown away to be downloaded again
+    pub corrupted_chunks: usize,
+    /// The number of times that a peer banned a source after getting bad
+    /// data
+    pub bans: usize,
+    /// The number of downloads that were aborted because the source left
+    /// or stopped uploading
+    pub aborted_downloads: usize,
+    /// The statistics of each swarm, if several files are distributed at
+    /// once
+    pub swarms: Vec<SwarmRound>,
     pub execution_time: Duration,
 }
 
+#[derive(Debug, Clone)]
+pub struct SwarmRound {
+    pub completed_peers: usize,
+    pub completed_chunks: usize,
+    pub exchanged_chunks: usize,
+}
+
+/// The state visible to a peer when it selects the next chunk to download.
+pub struct ChunkSelectionContext<'a> {
+    pub peer: &'a Peer,
+    pub peers: &'a [Peer],
+    pub file: &'a File,
+    /// All chunk indices ordered from the rarest to the most common chunk,
+    /// with chunks possessed by the same number of peers in random order.
+    /// With an overlay topology, only the neighbours of the peer are counted.
+    /// Only announced chunks are counted.
+    pub chunks_by_rarity: &'a [usize],
+    pub round_number: usize,
+}
+
+/// A policy that decides in which order a peer tries to download chunks.
+///
+/// The returned chunk indices are tried in order until a source with
+/// available capacity is found. Chunks that the peer already possesses
+/// are skipped, so they do not need to be filtered out.
+pub trait ChunkSelector: fmt::Debug {
+    fn select_chunks(&self, context: &ChunkSelectionContext, rng: &mut dyn RngCore) -> Vec<usize>;
+}
+
+#[derive(Debug)]
+pub struct RarestFirstSelector;
+#[derive(Debug)]
+pub struct LocalRarestFirstSelector {
+    sample_size: usize,
+}
+#[derive(Debug)]
+pub struct RandomFirstSelector {
+    initial_random_chunks: usize,
+}
+#[derive(Debug)]
+pub struct MostCommonFirstSelector;
+#[derive(Debug)]
+pub struct UniformSelector;
+#[derive(Debug)]
+pub struct StreamingSelector {
+    lookahead: f64,
+}
+
+/// The state visible to a peer when it selects the source of a chunk.
+pub struct SourceSelectionContext<'a> {
+    pub chunk_number: usize,
+    pub block_number: usize,
+    pub target_peer: usize,
+    pub peers: &'a [Peer],
+    pub number_seeds: usize,
+    /// All peer indices in random order, with the seeds before the other peers.
+    pub shuffled_peers: &'a [usize],
+}
+
+/// A policy that decides from which peer a chunk is downloaded.
+///
+/// The selected source must have available capacity for the chunk, as
+/// given by `SourceSelectionContext::available_capacity`. Returning `None`
+/// means that the peer moves on to the next chunk.
+pub trait SourceSelector: fmt::Debug {
+    fn select_source(
+        &self,
+        context: &SourceSelectionContext,
+        rng: &mut dyn RngCore,
+    ) -> Option<usize>;
+}
+
+#[derive(Debug)]
+pub struct NonSeedsFirstSelector;
+#[derive(Debug)]
+pub struct SeedsFirstSelector;
+#[derive(Debug)]
+pub struct FastestSourceSelector;
+#[derive(Debug)]
+pub struct LeastLoadedSourceSelector;
+#[derive(Debug)]
+pub struct RandomSourceSelector;
+#[derive(Debug)]
+pub struct LocalityPreferringSelector {
+    peer_locations: Vec<usize>,
+}
+
 pub trait RunObserver {
     fn random_seed(&self, _seed: u64) {}
     fn chunk_size(&self, _chunk_size: usize) {}
@@ -112,8 +589,15 @@ pub trait RunObserver {
         _target_peer: usize,
     ) {
     }
+    fn peer_joined(&self, _peer: usize) {}
+    fn peer_left(&self, _peer: usize) {}
     fn peer_completed(&self, _peer: usize) {}
+    fn swarm_completed(&self, _peer: usize, _swarm: usize) {}
+    fn tracker_announce(&self, _peer: usize, _new_peers: usize) {}
     fn chunk_completed(&self, _chunk_number: usize) {}
+    fn chunk_corrupted(&self, _peer: usize, _chunk_number: usize) {}
+    fn peer_banned(&self, _peer: usize, _banned_peer: usize) {}
+    fn download_aborted(&self, _chunk_number: usize, _source_peer: usize, _target_peer: usize) {}
     fn round_end(&self, _round_number: usize, _round: &Round) {}
 }
 
@@ -126,255 +610,1395 @@ impl PeerConfig {
         let selfishness = match config_string.first().unwrap_or(&b'a') {
             b's' => Selfishness::Selfish,
             b'f' => Selfishness::Freerider,
+            b't' => Selfishness::SeedForRounds(Self::parameter(config_string, 10)),
+            b'r' => Selfishness::SeedUntilRatio(Self::parameter(config_string, 1.0)),
             _ => Selfishness::Altruistic,
         };
         let strategy = match config_string.get(1).unwrap_or(&b'r') {
+            b'l' => Strategy::LocalRarestFirst,
+            b'f' => Strategy::RandomFirst,
+            b's' => Strategy::Streaming,
             b'm' => Strategy::MostCommonFirst,
             b'u' => Strategy::Uniform,
             _ => Strategy::RarestFirst,
         };
+        let chunk_selector = strategy.chunk_selector();
+        let source_selector = SourceStrategy::default().source_selector();
         let speed = match config_string.get(2).unwrap_or(&b'f') {
             b'm' => Speed::Medium,
             b's' => Speed::Slow,
             _ => Speed::Fast,
         };
+        let adversary = Self::words(config_string)
+            .find_map(|w| w.parse().ok())
+            .unwrap_or_default();
         PeerConfig {
             selfishness,
-            strategy,
+            chunk_selector,
+            source_selector,
             speed,
+            adversary,
         }
     }
+
+    fn parameter<T: FromStr>(config_string: &[u8], default: T) -> T {
+        Self::words(config_string)
+            .find_map(|w| w.parse().ok())
+            .unwrap_or(default)
+    }
+
+    /// The words that follow the three letters, which are the parameter
+    /// and the adversary.
+    fn words(config_string: &[u8]) -> impl Iterator<Item = &str> {
+        config_string
+            .get(3..)
+            .and_then(|p| str::from_utf8(p).ok())
+            .unwrap_or_default()
+            .split_whitespace()
+    }
+
+    pub fn with_selfishness(mut self, selfishness: Selfishness) -> PeerConfig {
+        self.selfishness = selfishness;
+        self
+    }
+
+    pub fn with_chunk_selector(mut self, chunk_selector: Rc<dyn ChunkSelector>) -> PeerConfig {
+        self.chunk_selector = chunk_selector;
+        self
+    }
+
+    pub fn with_source_selector(mut self, source_selector: Rc<dyn SourceSelector>) -> PeerConfig {
+        self.source_selector = source_selector;
+        self
+    }
+
+    pub fn with_adversary(mut self, adversary: Adversary) -> PeerConfig {
+        self.adversary = adversary;
+        self
+    }
 }
 
-impl Config {
-    fn assert_common_parameters(
-        number_chunks: usize,
-        number_peers: usize,
-        number_seeds: usize,
-        speed_fast: usize,
-        speed_medium: usize,
-        speed_slow: usize,
-    ) {
-        assert!(number_chunks > 0);
-        assert!(number_seeds > 0);
-        assert!(number_peers > number_seeds);
-        assert!(speed_slow > 0);
-        assert!(speed_medium >= speed_slow);
-        assert!(speed_fast >= speed_medium);
+impl Strategy {
+    pub fn chunk_selector(self) -> Rc<dyn ChunkSelector> {
+        match self {
+            Strategy::RarestFirst => Rc::new(RarestFirstSelector),
+            Strategy::LocalRarestFirst => Rc::new(LocalRarestFirstSelector::default()),
+            Strategy::RandomFirst => Rc::new(RandomFirstSelector::default()),
+            Strategy::Streaming => Rc::new(StreamingSelector::default()),
+            Strategy::MostCommonFirst => Rc::new(MostCommonFirstSelector),
+            Strategy::Uniform => Rc::new(UniformSelector),
+        }
     }
+}
 
-    #[allow(clippy::too_many_arguments)]
-    pub fn from_counts(
-        number_chunks: usize,
-        number_peers: usize,
-        number_seeds: usize,
-        speed_fast: usize,
-        speed_medium: usize,
-        speed_slow: usize,
-        number_selfish: usize,
-        number_freeriders: usize,
-        strategy: Strategy,
-    ) -> Config {
-        Self::assert_common_parameters(
-            number_chunks,
-            number_peers,
-            number_seeds,
-            speed_fast,
-            speed_medium,
-            speed_slow,
-        );
-        assert!(number_seeds + number_selfish + number_freeriders <= number_peers);
-        let speed_gcd = gcd(gcd(speed_slow, speed_medium), speed_fast);
-        let speed_fast = speed_fast / speed_gcd;
-        let speed_medium = speed_medium / speed_gcd;
-        let speed_slow = speed_slow / speed_gcd;
-        assert!(speed_fast <= 1000);
-        let chunk_size = lcm(lcm(speed_slow, speed_medium), speed_fast);
-        let mut selfishness =
-            vec![Selfishness::Altruistic; number_peers - number_selfish - number_freeriders];
-        selfishness.extend(vec![Selfishness::Selfish; number_selfish]);
-        selfishness.extend(vec![Selfishness::Freerider; number_freeriders]);
-        Config {
-            number_chunks,
-            number_peers,
-            number_seeds,
-            chunk_size,
-            peer_selfishness: selfishness,
-            peer_strategies: vec![strategy; number_peers],
-            peer_speeds: vec![speed_fast / speed_gcd; number_peers],
+impl SourceStrategy {
+    pub fn source_selector(self) -> Rc<dyn SourceSelector> {
+        match self {
+            SourceStrategy::NonSeedsFirst => Rc::new(NonSeedsFirstSelector),
+            SourceStrategy::SeedsFirst => Rc::new(SeedsFirstSelector),
+            SourceStrategy::Fastest => Rc::new(FastestSourceSelector),
+            SourceStrategy::LeastLoaded => Rc::new(LeastLoadedSourceSelector),
+            SourceStrategy::Random => Rc::new(RandomSourceSelector),
         }
     }
+}
 
-    pub fn from_peer_config(
-        number_chunks: usize,
-        number_peers: usize,
-        number_seeds: usize,
-        speed_fast: usize,
-        speed_medium: usize,
-        speed_slow: usize,
-        peer_config: Vec<PeerConfig>,
-    ) -> Config {
-        Self::assert_common_parameters(
-            number_chunks,
-            number_peers,
-            number_seeds,
-            speed_fast,
-            speed_medium,
-            speed_slow,
-        );
-        assert!(peer_config.len() <= number_peers - number_seeds);
-        let speed_gcd = gcd(gcd(speed_slow, speed_medium), speed_fast);
-        let speed_fast = speed_fast / speed_gcd;
-        let speed_medium = speed_medium / speed_gcd;
-        let speed_slow = speed_slow / speed_gcd;
-        assert!(speed_fast <= 1000);
-        let chunk_size = lcm(lcm(speed_slow, speed_medium), speed_fast);
-        let mut peer_selfishness = vec![Selfishness::Altruistic; number_seeds];
-        peer_selfishness.extend(peer_config.iter().map(|c| c.selfishness));
-        peer_selfishness.extend(vec![
-            Selfishness::default();
-            number_peers - peer_selfishness.len()
-        ]);
-        let mut peer_strategies = vec![Strategy::default(); number_seeds];
-        peer_strategies.extend(peer_config.iter().map(|c| c.strategy));
-        peer_strategies.extend(vec![
-            Strategy::default();
-            number_peers - peer_strategies.len()
-        ]);
-        let mut peer_speeds = vec![Speed::Fast; number_seeds];
-        peer_speeds.extend(peer_config.iter().map(|c| c.speed));
-        peer_speeds.extend(vec![Speed::default(); number_peers - peer_speeds.len()]);
-        let peer_speeds = peer_speeds
+impl ChunkSelector for RarestFirstSelector {
+    fn select_chunks(&self, context: &ChunkSelectionContext, _rng: &mut dyn RngCore) -> Vec<usize> {
+        context.chunks_by_rarity.to_vec()
+    }
+}
+
+impl LocalRarestFirstSelector {
+    /// Creates a selector where a peer counts the chunks of `sample_size`
+    /// random peers among the active peers it knows, which are its
+    /// neighbours with an overlay topology and all peers otherwise.
+    pub fn new(sample_size: usize) -> LocalRarestFirstSelector {
+        assert!(sample_size > 0);
+        LocalRarestFirstSelector { sample_size }
+    }
+}
+
+impl Default for LocalRarestFirstSelector {
+    fn default() -> Self {
+        LocalRarestFirstSelector::new(DEFAULT_RARITY_SAMPLE_SIZE)
+    }
+}
+
+impl ChunkSelector for LocalRarestFirstSelector {
+    fn select_chunks(&self, context: &ChunkSelectionContext, rng: &mut dyn RngCore) -> Vec<usize> {
+        let known_peers: Vec<&Peer> = context
+            .peers
             .iter()
-            .map(|s| match s {
-                Speed::Fast => speed_fast,
-                Speed::Medium => speed_medium,
-                Speed::Slow => speed_slow,
+            .filter(|p| {
+                p.index != context.peer.index && p.is_active && context.peer.is_neighbour(p.index)
             })
             .collect();
-        Config {
-            number_chunks,
-            number_peers,
-            number_seeds,
-            chunk_size,
-            peer_selfishness,
-            peer_strategies,
-            peer_speeds,
+        let mut availability = vec![0; context.file.chunks.len()];
+        for peer in known_peers.choose_multiple(rng, self.sample_size) {
+            for (count, possessed) in availability.iter_mut().zip(&peer.announced_chunks) {
+                if *possessed {
+                    *count += 1;
+                }
+            }
         }
+        let mut chunks = context.chunks_by_rarity.to_vec();
+        chunks.shuffle(rng);
+        chunks.sort_by_key(|c| availability[*c]);
+        chunks
     }
 }
 
-impl Chunk {
-    pub fn new(index: usize, number_seeds: usize) -> Chunk {
-        Chunk {
-            index,
-            completion_round: None,
-            number_possessing_peers: number_seeds,
+impl RandomFirstSelector {
+    /// Creates a selector where a peer picks chunks randomly until it
+    /// possesses `initial_random_chunks` chunks, and then picks the rarest
+    /// chunks first.
+    pub fn new(initial_random_chunks: usize) -> RandomFirstSelector {
+        RandomFirstSelector {
+            initial_random_chunks,
         }
     }
 }
 
-impl Peer {
-    pub fn new(
-        index: usize,
-        file: &File,
-        is_seed: bool,
-        selfishness: Selfishness,
-        strategy: Strategy,
-        speed: usize,
-    ) -> Peer {
-        assert!(!is_seed || selfishness == Selfishness::Altruistic);
-        Peer {
-            index,
-            selfishness,
-            strategy,
-            speed,
-            completion_round: if is_seed { Some(0) } else { None },
-            possessed_chunks: vec![is_seed; file.chunks.len()],
-            number_uploads: 0,
-            current_uploads: vec![],
-            current_download: None,
-        }
+impl Default for RandomFirstSelector {
+    fn default() -> Self {
+        RandomFirstSelector::new(DEFAULT_INITIAL_RANDOM_CHUNKS)
     }
+}
 
-    fn available_capacity_for_chunk(&self, chunk_number: usize, target_peer: usize) -> usize {
-        let allows_download = self.selfishness == Selfishness::Altruistic
-            || (self.selfishness == Selfishness::Selfish && self.completion_round.is_none());
-        let has_chunk = self.possessed_chunks[chunk_number];
-        if allows_download && has_chunk {
-            let used_capacity: usize = self
-                .current_uploads
-                .iter()
-                .filter_map(|u| {
-                    if u.target_peer == target_peer {
-                        None
-                    } else {
-                        Some(u.current_size)
-                    }
-                })
-                .sum();
-            if used_capacity > self.speed {
-                0
-            } else {
-                self.speed - used_capacity
-            }
+impl ChunkSelector for RandomFirstSelector {
+    fn select_chunks(&self, context: &ChunkSelectionContext, rng: &mut dyn RngCore) -> Vec<usize> {
+        let number_possessed = context.peer.possessed_chunks.iter().filter(|c| **c).count();
+        if number_possessed < self.initial_random_chunks {
+            UniformSelector.select_chunks(context, rng)
         } else {
-            0
+            RarestFirstSelector.select_chunks(context, rng)
         }
     }
+}
 
-    fn index_of_upload(&self, chunk_number: usize, target_peer: usize) -> Option<usize> {
-        self.current_uploads
-            .iter()
-            .position(|u| u.chunk_number == chunk_number && u.target_peer == target_peer)
+impl ChunkSelector for MostCommonFirstSelector {
+    fn select_chunks(&self, context: &ChunkSelectionContext, _rng: &mut dyn RngCore) -> Vec<usize> {
+        context.chunks_by_rarity.iter().rev().copied().collect()
     }
+}
 
-    fn download(&mut self, download: Download) {
-        if let Some(index) = self.index_of_upload(download.chunk_number, download.target_peer) {
-            self.current_uploads[index] = download
-        } else {
-            self.current_uploads.push(download)
-        }
+impl ChunkSelector for UniformSelector {
+    fn select_chunks(&self, context: &ChunkSelectionContext, rng: &mut dyn RngCore) -> Vec<usize> {
+        let mut chunks = context.chunks_by_rarity.to_vec();
+        chunks.shuffle(rng);
+        chunks
     }
+}
 
-    fn chunk_upload_finished(&mut self, chunk_number: usize, target_peer: usize) {
-        if let Some(index) = self.index_of_upload(chunk_number, target_peer) {
-            self.number_uploads += 1;
-            self.current_uploads.remove(index);
+impl StreamingSelector {
+    /// Creates a selector where a peer downloads the chunks whose playback
+    /// deadline is at most `lookahead` rounds away in the order of their
+    /// deadlines, and then the other chunks from the rarest to the most
+    /// common. Without playback, the peer downloads all chunks in order.
+    pub fn new(lookahead: f64) -> StreamingSelector {
+        assert!(lookahead >= 0.0);
+        StreamingSelector { lookahead }
+    }
+}
+
+impl Default for StreamingSelector {
+    fn default() -> Self {
+        StreamingSelector::new(DEFAULT_STREAMING_LOOKAHEAD)
+    }
+}
+
+impl ChunkSelector for StreamingSelector {
+    fn select_chunks(&self, context: &ChunkSelectionContext, _rng: &mut dyn RngCore) -> Vec<usize> {
+        let horizon = context.round_number as f64 + self.lookahead;
+        let (mut urgent_chunks, other_chunks): (Vec<usize>, Vec<usize>) =
+            context.chunks_by_rarity.iter().partition(|c| {
+                context
+                    .peer
+                    .chunk_deadline(**c)
+                    .is_none_or(|d| d <= horizon)
+            });
+        urgent_chunks.sort();
+        urgent_chunks.extend(other_chunks);
+        urgent_chunks
+    }
+}
+
+impl SourceSelectionContext<'_> {
+    pub fn available_capacity(&self, source_peer: usize) -> usize {
+        self.peers[source_peer].available_capacity_for_block(
+            self.chunk_number,
+            self.block_number,
+            self.target_peer,
+        )
+    }
+
+    /// The available capacity that a source claims to have, which is more
+    /// or less than the actual capacity if the source lies about it.
+    pub fn advertised_capacity(&self, source_peer: usize) -> usize {
+        let available_capacity = self.available_capacity(source_peer);
+        match self.peers[source_peer].adversary {
+            Adversary::CapacityLiar(factor) => (available_capacity as f64 * factor) as usize,
+            _ => available_capacity,
         }
     }
 
-    fn check_chunk_download_finished(&mut self, chunk_size: usize) -> Option<Download> {
-        if let Some(download) = self.current_download {
-            if download.downloaded_size >= chunk_size {
-                self.possessed_chunks[download.chunk_number] = true;
-                self.current_download = None;
-                Some(download)
-            } else {
-                None
-            }
+    pub fn is_seed(&self, peer: usize) -> bool {
+        peer < self.number_seeds
+    }
+
+    /// The peers with available capacity for the block in the order of
+    /// `shuffled_peers`, excluding the peers that the target peer is already
+    /// downloading the block from and the peers whose requests from it timed
+    /// out.
+    pub fn candidate_sources(&self) -> impl Iterator<Item = usize> + '_ {
+        let target = &self.peers[self.target_peer];
+        self.shuffled_peers.iter().copied().filter(move |p| {
+            *p != self.target_peer
+                && !target.is_downloading_from(self.chunk_number, self.block_number, *p)
+                && !target.timed_out_sources.contains(p)
+                && self.available_capacity(*p) > 0
+        })
+    }
+}
+
+impl SourceSelector for NonSeedsFirstSelector {
+    fn select_source(
+        &self,
+        context: &SourceSelectionContext,
+        _rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        let mut candidates = context.candidate_sources();
+        let first_candidate = candidates.next()?;
+        if context.is_seed(first_candidate) {
+            candidates
+                .find(|p| !context.is_seed(*p))
+                .or(Some(first_candidate))
         } else {
-            None
+            Some(first_candidate)
         }
     }
 }
 
-impl Distribution {
+impl SourceSelector for SeedsFirstSelector {
+    fn select_source(
+        &self,
+        context: &SourceSelectionContext,
+        _rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        context.candidate_sources().next()
+    }
+}
+
+impl SourceSelector for FastestSourceSelector {
+    fn select_source(
+        &self,
+        context: &SourceSelectionContext,
+        _rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        context
+            .candidate_sources()
+            .max_by_key(|p| context.advertised_capacity(*p))
+    }
+}
+
+impl SourceSelector for LeastLoadedSourceSelector {
+    fn select_source(
+        &self,
+        context: &SourceSelectionContext,
+        _rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        context
+            .candidate_sources()
+            .min_by_key(|p| context.peers[*p].current_uploads.len())
+    }
+}
+
+impl SourceSelector for RandomSourceSelector {
+    fn select_source(
+        &self,
+        context: &SourceSelectionContext,
+        rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        let candidates: Vec<usize> = context.candidate_sources().collect();
+        candidates.choose(rng).copied()
+    }
+}
+
+impl LocalityPreferringSelector {
+    /// Creates a selector where `peer_locations[i]` is the location of peer
+    /// `i`. Sources in the same location as the downloading peer are
+    /// preferred over all other sources.
+    pub fn new(peer_locations: Vec<usize>) -> LocalityPreferringSelector {
+        LocalityPreferringSelector { peer_locations }
+    }
+}
+
+impl SourceSelector for LocalityPreferringSelector {
+    fn select_source(
+        &self,
+        context: &SourceSelectionContext,
+        _rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        let target_location = self.peer_locations[context.target_peer];
+        let mut candidates = context.candidate_sources();
+        let first_candidate = candidates.next()?;
+        if self.peer_locations[first_candidate] == target_location {
+            Some(first_candidate)
+        } else {
+            candidates
+                .find(|p| self.peer_locations[*p] == target_location)
+                .or(Some(first_candidate))
+        }
+    }
+}
+
+impl ChokingConfig {
+    /// Peers upload to at most `upload_slots` peers at a time. One of the
+    /// slots is the optimistic unchoke, which is rotated to a random peer
+    /// every `optimistic_unchoke_interval` rounds. The other slots are
+    /// re-evaluated every `rechoke_interval` rounds and given to the peers
+    /// that uploaded the most to this peer since the previous evaluation.
+    pub fn new(
+        upload_slots: usize,
+        rechoke_interval: usize,
+        optimistic_unchoke_interval: usize,
+    ) -> ChokingConfig {
+        assert!(upload_slots > 0);
+        assert!(rechoke_interval > 0);
+        assert!(optimistic_unchoke_interval > 0);
+        ChokingConfig {
+            upload_slots,
+            rechoke_interval,
+            optimistic_unchoke_interval,
+        }
+    }
+}
+
+impl Default for ChokingConfig {
+    fn default() -> Self {
+        ChokingConfig::new(4, 1, 3)
+    }
+}
+
+impl TrackerConfig {
+    /// Peers know only the peers they have received from the tracker. A
+    /// peer announces itself to the tracker when it joins and again every
+    /// `announce_interval` rounds, and each time receives at most
+    /// `peer_list_size` random active peers.
+    pub fn new(peer_list_size: usize, announce_interval: usize) -> TrackerConfig {
+        assert!(peer_list_size > 0);
+        assert!(announce_interval > 0);
+        TrackerConfig {
+            peer_list_size,
+            announce_interval,
+        }
+    }
+}
+
+impl LiveConfig {
+    /// The seeds generate a new chunk every `chunk_interval` rounds, starting
+    /// from the first chunk at the beginning. Peers only download the latest
+    /// `window` chunks, and a chunk that falls out of the window before a
+    /// peer has downloaded it is missed.
+    pub fn new(chunk_interval: usize, window: usize) -> LiveConfig {
+        assert!(chunk_interval > 0);
+        assert!(window > 0);
+        LiveConfig {
+            chunk_interval,
+            window,
+        }
+    }
+}
+
+impl PlaybackConfig {
+    /// Peers play the file at `playback_rate` chunks per round while
+    /// downloading it. Playback starts `startup_delay` rounds after a peer
+    /// joins, or later if the peer does not have the first chunk by then,
+    /// and stalls whenever the next chunk to play has not been downloaded.
+    pub fn new(playback_rate: f64, startup_delay: usize) -> PlaybackConfig {
+        assert!(playback_rate > 0.0);
+        PlaybackConfig {
+            playback_rate,
+            startup_delay,
+        }
+    }
+}
+
+impl SwarmConfig {
+    /// The file consists of `number_chunks` chunks, and the non-seed peers
+    /// in `peers` take part in its swarm. The seeds take part in all swarms.
+    pub fn new(number_chunks: usize, peers: Vec<usize>) -> SwarmConfig {
+        assert!(number_chunks > 0);
+        SwarmConfig {
+            number_chunks,
+            peers,
+        }
+    }
+}
+
+impl DepartureConfig {
+    /// Peers that have completed the download leave after seeding for
+    /// `seeding_rounds` rounds, unless their `Selfishness` gives another
+    /// seeding policy, and peers that are still downloading abort
+    /// the download and leave with probability `abort_probability` in each
+    /// round. The initial seeds never leave.
+    pub fn new(seeding_rounds: Option<usize>, abort_probability: f64) -> DepartureConfig {
+        assert!((0.0..=1.0).contains(&abort_probability));
+        DepartureConfig {
+            seeding_rounds,
+            abort_probability,
+        }
+    }
+}
+
+impl SpeedTiers {
+    /// Creates tiers where the upload and download speeds of each tier are
+    /// the same.
+    pub fn new(speed_fast: usize, speed_medium: usize, speed_slow: usize) -> SpeedTiers {
+        Self::assert_speeds(speed_fast, speed_medium, speed_slow);
+        SpeedTiers {
+            fast: TierSpeed {
+                upload: speed_fast,
+                download: speed_fast,
+            },
+            medium: TierSpeed {
+                upload: speed_medium,
+                download: speed_medium,
+            },
+            slow: TierSpeed {
+                upload: speed_slow,
+                download: speed_slow,
+            },
+        }
+    }
+
+    /// Replaces the upload speeds of the tiers, leaving the download speeds
+    /// as they were. The upload speeds need not be ordered like the tiers,
+    /// as a peer may have a fast download but a slow upload.
+    pub fn with_upload_speeds(
+        mut self,
+        speed_fast: usize,
+        speed_medium: usize,
+        speed_slow: usize,
+    ) -> SpeedTiers {
+        assert!(speed_fast > 0 && speed_medium > 0 && speed_slow > 0);
+        self.fast.upload = speed_fast;
+        self.medium.upload = speed_medium;
+        self.slow.upload = speed_slow;
+        self
+    }
+
+    fn assert_speeds(speed_fast: usize, speed_medium: usize, speed_slow: usize) {
+        assert!(speed_slow > 0);
+        assert!(speed_medium >= speed_slow);
+        assert!(speed_fast >= speed_medium);
+    }
+
+    fn tier(&self, speed: Speed) -> TierSpeed {
+        match speed {
+            Speed::Fast => self.fast,
+            Speed::Medium => self.medium,
+            Speed::Slow => self.slow,
+        }
+    }
+}
+
+impl SpeedDistribution {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match self {
+            SpeedDistribution::Uniform { min, max } => rng.gen_range(*min..=*max),
+            SpeedDistribution::LogNormal { mu, sigma } => {
+                // Box-Muller transform
+                let u1: f64 = 1.0 - rng.gen::<f64>();
+                let u2: f64 = rng.gen();
+                let normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                (mu + sigma * normal).exp()
+            }
+            SpeedDistribution::Empirical(speeds) => *speeds.choose(rng).unwrap(),
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            SpeedDistribution::Uniform { min, max } => *min > 0.0 && max >= min,
+            SpeedDistribution::LogNormal { sigma, .. } => *sigma >= 0.0,
+            SpeedDistribution::Empirical(speeds) => {
+                !speeds.is_empty() && speeds.iter().all(|s| *s > 0.0)
+            }
+        }
+    }
+}
+
+impl FromStr for SpeedDistribution {
+    type Err = String;
+
+    /// Parses `uniform:MIN:MAX`, `lognormal:MU:SIGMA`, or
+    /// `empirical:SPEED,SPEED,...`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |p: &str| {
+            p.parse::<f64>()
+                .map_err(|_| format!("Invalid speed parameter {p}"))
+        };
+        let parts: Vec<&str> = s.split(':').collect();
+        let distribution = match parts.as_slice() {
+            ["uniform", min, max] => SpeedDistribution::Uniform {
+                min: parse(min)?,
+                max: parse(max)?,
+            },
+            ["lognormal", mu, sigma] => SpeedDistribution::LogNormal {
+                mu: parse(mu)?,
+                sigma: parse(sigma)?,
+            },
+            ["empirical", speeds] => SpeedDistribution::Empirical(
+                speeds.split(',').map(parse).collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(format!("Invalid speed distribution {s}")),
+        };
+        if distribution.is_valid() {
+            Ok(distribution)
+        } else {
+            Err(format!("Invalid speed distribution parameters {s}"))
+        }
+    }
+}
+
+impl Config {
+    fn assert_common_parameters(number_chunks: usize, number_peers: usize, number_seeds: usize) {
+        assert!(number_chunks > 0);
+        assert!(number_seeds > 0);
+        assert!(number_peers > number_seeds);
+    }
+
+    pub fn from_counts(
+        number_chunks: usize,
+        number_peers: usize,
+        number_seeds: usize,
+        speed_tiers: SpeedTiers,
+        number_selfish: usize,
+        number_freeriders: usize,
+        strategy: Strategy,
+    ) -> Config {
+        Self::assert_common_parameters(number_chunks, number_peers, number_seeds);
+        assert!(number_seeds + number_selfish + number_freeriders <= number_peers);
+        let mut selfishness =
+            vec![Selfishness::Altruistic; number_peers - number_selfish - number_freeriders];
+        selfishness.extend(vec![Selfishness::Selfish; number_selfish]);
+        selfishness.extend(vec![Selfishness::Freerider; number_freeriders]);
+        let peer_adversaries = vec![Adversary::Honest; number_peers];
+        Config {
+            number_chunks,
+            number_peers,
+            number_seeds,
+            peer_selfishness: selfishness,
+            peer_chunk_selectors: vec![strategy.chunk_selector(); number_peers],
+            peer_source_selectors: vec![SourceStrategy::default().source_selector(); number_peers],
+            peer_upload_speeds: vec![speed_tiers.fast.upload as f64; number_peers],
+            peer_download_speeds: vec![speed_tiers.fast.download as f64; number_peers],
+            choking: None,
+            arrivals: ArrivalSchedule::default(),
+            departures: DepartureConfig::default(),
+            topology: Topology::default(),
+            tracker: None,
+            engine: Engine::default(),
+            announcement_delay: 0,
+            endgame_chunks: None,
+            playback: None,
+            live: None,
+            super_seeding: false,
+            swarms: vec![],
+            chunk_sizes: None,
+            blocks_per_chunk: 1,
+            peer_malicious: vec![false; number_peers],
+            peer_adversaries,
+            corruption_probability: 0.0,
+            ban_threshold: None,
+            coding: None,
+            partial_progress: PartialProgress::Drop,
+        }
+    }
+
+    pub fn from_peer_config(
+        number_chunks: usize,
+        number_peers: usize,
+        number_seeds: usize,
+        speed_tiers: SpeedTiers,
+        peer_config: Vec<PeerConfig>,
+    ) -> Config {
+        Self::assert_common_parameters(number_chunks, number_peers, number_seeds);
+        assert!(peer_config.len() <= number_peers - number_seeds);
+        let mut peer_selfishness = vec![Selfishness::Altruistic; number_seeds];
+        peer_selfishness.extend(peer_config.iter().map(|c| c.selfishness));
+        peer_selfishness.extend(vec![
+            Selfishness::default();
+            number_peers - peer_selfishness.len()
+        ]);
+        let mut peer_chunk_selectors = vec![Strategy::default().chunk_selector(); number_seeds];
+        peer_chunk_selectors.extend(peer_config.iter().map(|c| c.chunk_selector.clone()));
+        peer_chunk_selectors.extend(vec![
+            Strategy::default().chunk_selector();
+            number_peers - peer_chunk_selectors.len()
+        ]);
+        let mut peer_source_selectors =
+            vec![SourceStrategy::default().source_selector(); number_seeds];
+        peer_source_selectors.extend(peer_config.iter().map(|c| c.source_selector.clone()));
+        peer_source_selectors.extend(vec![
+            SourceStrategy::default().source_selector();
+            number_peers - peer_source_selectors.len()
+        ]);
+        let mut peer_adversaries = vec![Adversary::Honest; number_seeds];
+        peer_adversaries.extend(peer_config.iter().map(|c| c.adversary));
+        peer_adversaries.extend(vec![
+            Adversary::default();
+            number_peers - peer_adversaries.len()
+        ]);
+        let mut peer_speeds = vec![Speed::Fast; number_seeds];
+        peer_speeds.extend(peer_config.iter().map(|c| c.speed));
+        peer_speeds.extend(vec![Speed::default(); number_peers - peer_speeds.len()]);
+        let peer_upload_speeds = peer_speeds
+            .iter()
+            .map(|s| speed_tiers.tier(*s).upload as f64)
+            .collect();
+        let peer_download_speeds = peer_speeds
+            .iter()
+            .map(|s| speed_tiers.tier(*s).download as f64)
+            .collect();
+        Config {
+            number_chunks,
+            number_peers,
+            number_seeds,
+            peer_selfishness,
+            peer_chunk_selectors,
+            peer_source_selectors,
+            peer_upload_speeds,
+            peer_download_speeds,
+            choking: None,
+            arrivals: ArrivalSchedule::default(),
+            departures: DepartureConfig::default(),
+            topology: Topology::default(),
+            tracker: None,
+            engine: Engine::default(),
+            announcement_delay: 0,
+            endgame_chunks: None,
+            playback: None,
+            live: None,
+            super_seeding: false,
+            swarms: vec![],
+            chunk_sizes: None,
+            blocks_per_chunk: 1,
+            peer_malicious: vec![false; number_peers],
+            peer_adversaries,
+            corruption_probability: 0.0,
+            ban_threshold: None,
+            coding: None,
+            partial_progress: PartialProgress::Drop,
+        }
+    }
+
+    pub fn set_speeds(&mut self, peer: usize, upload_speed: f64, download_speed: f64) {
+        assert!(upload_speed > 0.0 && download_speed > 0.0);
+        self.peer_upload_speeds[peer] = upload_speed;
+        self.peer_download_speeds[peer] = download_speed;
+    }
+
+    /// Draws the speed of every peer from `distribution`, using the same
+    /// speed for uploads and downloads.
+    pub fn sample_speeds<R: Rng + ?Sized>(
+        &mut self,
+        distribution: &SpeedDistribution,
+        rng: &mut R,
+    ) {
+        assert!(distribution.is_valid());
+        for peer in 0..self.number_peers {
+            let speed = distribution.sample(rng);
+            self.set_speeds(peer, speed, speed);
+        }
+    }
+
+    /// Draws the upload speed of every peer from `distribution`, leaving
+    /// the download speeds as they were.
+    pub fn sample_upload_speeds<R: Rng + ?Sized>(
+        &mut self,
+        distribution: &SpeedDistribution,
+        rng: &mut R,
+    ) {
+        assert!(distribution.is_valid());
+        for peer in 0..self.number_peers {
+            self.peer_upload_speeds[peer] = distribution.sample(rng);
+        }
+    }
+
+    /// Splits the file into `number_files` files of nearly equal numbers of
+    /// chunks, each distributed in its own swarm. Every non-seed peer takes
+    /// part in each swarm with `probability`, and in at least one swarm.
+    pub fn sample_swarms<R: Rng + ?Sized>(
+        &mut self,
+        number_files: usize,
+        probability: f64,
+        rng: &mut R,
+    ) {
+        assert!(number_files > 0 && number_files <= self.number_chunks);
+        assert!(probability > 0.0 && probability <= 1.0);
+        let mut swarm_peers = vec![vec![]; number_files];
+        for peer in self.number_seeds..self.number_peers {
+            let mut swarms: Vec<usize> = (0..number_files)
+                .filter(|_| rng.gen_bool(probability))
+                .collect();
+            if swarms.is_empty() {
+                swarms.push(rng.gen_range(0..number_files));
+            }
+            for swarm in swarms {
+                swarm_peers[swarm].push(peer);
+            }
+        }
+        let swarms = swarm_peers
+            .into_iter()
+            .enumerate()
+            .map(|(i, peers)| {
+                let number_chunks = self.number_chunks / number_files
+                    + usize::from(i < self.number_chunks % number_files);
+                SwarmConfig::new(number_chunks, peers)
+            })
+            .collect();
+        self.set_swarms(swarms);
+    }
+
+    pub fn set_chunk_selector(&mut self, peer: usize, chunk_selector: Rc<dyn ChunkSelector>) {
+        self.peer_chunk_selectors[peer] = chunk_selector;
+    }
+
+    pub fn set_source_selector(&mut self, peer: usize, source_selector: Rc<dyn SourceSelector>) {
+        self.peer_source_selectors[peer] = source_selector;
+    }
+
+    pub fn set_choking(&mut self, choking: ChokingConfig) {
+        self.choking = Some(choking);
+    }
+
+    pub fn set_arrivals(&mut self, arrivals: ArrivalSchedule) {
+        if let ArrivalSchedule::Explicit(join_rounds) = &arrivals {
+            assert!(join_rounds.len() <= self.number_peers - self.number_seeds);
+        }
+        if let ArrivalSchedule::Poisson { rate } = arrivals {
+            assert!(rate > 0.0);
+        }
+        self.arrivals = arrivals;
+    }
+
+    pub fn set_departures(&mut self, departures: DepartureConfig) {
+        self.departures = departures;
+    }
+
+    pub fn set_topology(&mut self, topology: Topology) {
+        assert!(self.tracker.is_none() || matches!(topology, Topology::Complete));
+        let number_peers = self.number_peers;
+        match &topology {
+            Topology::Complete => {}
+            Topology::RandomRegular { degree } => {
+                assert!(*degree > 0 && *degree < number_peers);
+                assert!((degree * number_peers).is_multiple_of(2));
+            }
+            Topology::ErdosRenyi { probability } => {
+                assert!((0.0..=1.0).contains(probability));
+            }
+            Topology::SmallWorld {
+                degree,
+                rewiring_probability,
+            } => {
+                assert!(*degree > 0 && *degree < number_peers && degree % 2 == 0);
+                assert!((0.0..=1.0).contains(rewiring_probability));
+            }
+            Topology::ScaleFree { edges_per_peer } => {
+                assert!(*edges_per_peer > 0 && *edges_per_peer < number_peers);
+            }
+            Topology::Edges(edges) => {
+                assert!(edges
+                    .iter()
+                    .all(|(a, b)| a != b && *a < number_peers && *b < number_peers));
+            }
+        }
+        self.topology = topology;
+    }
+
+    /// Makes peers learn about other peers from a tracker instead of
+    /// knowing all peers. This cannot be combined with an overlay topology.
+    pub fn set_tracker(&mut self, tracker: TrackerConfig) {
+        assert!(matches!(self.topology, Topology::Complete));
+        self.tracker = Some(tracker);
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        if let Engine::Events { latency } = engine {
+            assert!(latency >= 0.0);
+        }
+        self.engine = engine;
+    }
+
+    /// Delays the announcement of a downloaded chunk to the other peers by
+    /// `announcement_delay` rounds. Until then, the other peers neither
+    /// count the chunk when ordering chunks by rarity nor download it from
+    /// the peer.
+    pub fn set_announcement_delay(&mut self, announcement_delay: usize) {
+        self.announcement_delay = announcement_delay;
+    }
+
+    /// Enables endgame mode, where a peer that is missing at most
+    /// `endgame_chunks` chunks also downloads the chunks it is already
+    /// downloading from other sources. When one download of a chunk
+    /// finishes, the other downloads of the same chunk are cancelled.
+    pub fn set_endgame(&mut self, endgame_chunks: usize) {
+        assert!(endgame_chunks > 0);
+        self.endgame_chunks = Some(endgame_chunks);
+    }
+
+    /// Makes the seeds generate the file over time as a live stream.
+    pub fn set_live(&mut self, live: LiveConfig) {
+        assert!(self.swarms.is_empty() && self.coding.is_none());
+        self.live = Some(live);
+    }
+
+    /// Makes the seeds offer each chunk to only one peer at a time, and
+    /// offer a peer a new chunk only after the chunk previously offered to
+    /// it has been uploaded further by another peer.
+    pub fn set_super_seeding(&mut self, super_seeding: bool) {
+        self.super_seeding = super_seeding;
+    }
+
+    /// Makes the non-seed peers play the file while downloading it.
+    pub fn set_playback(&mut self, playback: PlaybackConfig) {
+        assert!(self.swarms.is_empty() && self.coding.is_none());
+        self.playback = Some(playback);
+    }
+
+    /// Gives each chunk its own size, relative to the default chunk size
+    /// that all chunks have otherwise.
+    pub fn set_chunk_sizes(&mut self, chunk_sizes: Vec<f64>) {
+        assert!(self.coding.is_none());
+        assert_eq!(chunk_sizes.len(), self.number_chunks);
+        assert!(chunk_sizes.iter().all(|s| *s > 0.0));
+        self.chunk_sizes = Some(chunk_sizes);
+    }
+
+    /// Splits each chunk into `blocks_per_chunk` blocks. A peer can download
+    /// the blocks of a chunk from several sources at the same time, and it
+    /// has the chunk when it has all of its blocks.
+    pub fn set_blocks_per_chunk(&mut self, blocks_per_chunk: usize) {
+        assert!(blocks_per_chunk > 0);
+        self.blocks_per_chunk = blocks_per_chunk;
+    }
+
+    /// Makes a non-seed peer upload only bad data, so that every chunk with
+    /// a block from it fails the hash check.
+    pub fn set_malicious(&mut self, peer: usize, is_malicious: bool) {
+        assert!(peer >= self.number_seeds);
+        self.peer_malicious[peer] = is_malicious;
+    }
+
+    /// Makes a non-seed peer behave as the given kind of adversary.
+    pub fn set_adversary(&mut self, peer: usize, adversary: Adversary) {
+        assert!(peer >= self.number_seeds);
+        if let Adversary::CapacityLiar(factor) = adversary {
+            assert!(factor > 0.0);
+        }
+        self.peer_adversaries[peer] = adversary;
+    }
+
+    /// Returns whether any peer is malicious or another kind of adversary.
+    pub fn has_adversaries(&self) -> bool {
+        self.peer_malicious.contains(&true)
+            || self
+                .peer_adversaries
+                .iter()
+                .any(|a| *a != Adversary::Honest)
+    }
+
+    /// Makes every peer honest, to compare a distribution with adversaries
+    /// to the same distribution without them.
+    pub fn remove_adversaries(&mut self) {
+        self.peer_malicious.fill(false);
+        self.peer_adversaries.fill(Adversary::Honest);
+    }
+
+    /// Makes a downloaded chunk fail its hash check with
+    /// `corruption_probability`, in which case it is downloaded again.
+    pub fn set_corruption_probability(&mut self, corruption_probability: f64) {
+        assert!((0.0..1.0).contains(&corruption_probability));
+        self.corruption_probability = corruption_probability;
+    }
+
+    /// Makes a peer ban a source after `ban_threshold` chunks that it sent
+    /// blocks of failed the hash check.
+    pub fn set_ban_threshold(&mut self, ban_threshold: usize) {
+        assert!(ban_threshold > 0);
+        self.ban_threshold = Some(ban_threshold);
+    }
+
+    /// Makes the peers exchange coded chunks instead of the chunks of the
+    /// file. This cannot be combined with chunks of different sizes,
+    /// several files, live streaming, or playback, and network coding
+    /// cannot be combined with blocks or super-seeding either.
+    pub fn set_coding(&mut self, coding: Coding) {
+        assert!(self.chunk_sizes.is_none() && self.swarms.is_empty());
+        assert!(self.live.is_none() && self.playback.is_none());
+        if let Coding::Erasure { coded_chunks } = coding {
+            assert!(coded_chunks > self.number_chunks);
+        }
+        self.coding = Some(coding);
+    }
+
+    /// Makes the peers exchange the chunks of the file, to compare a
+    /// distribution with coding to the same distribution without it.
+    pub fn remove_coding(&mut self) {
+        self.coding = None;
+    }
+
+    /// Sets what a peer does with the part of a block that it has
+    /// downloaded when the source leaves or stops uploading.
+    pub fn set_partial_progress(&mut self, partial_progress: PartialProgress) {
+        self.partial_progress = partial_progress;
+    }
+
+    /// Makes the file `file_length` bytes long, split into chunks of
+    /// `chunk_length` bytes, so that the last chunk is shorter than the
+    /// others unless the file length is a multiple of the chunk length.
+    pub fn set_file_length(&mut self, file_length: usize, chunk_length: usize) {
+        assert!(chunk_length > 0);
+        assert_eq!(file_length.div_ceil(chunk_length), self.number_chunks);
+        let mut chunk_sizes = vec![1.0; self.number_chunks];
+        let last_chunk_length = file_length - (self.number_chunks - 1) * chunk_length;
+        chunk_sizes[self.number_chunks - 1] = last_chunk_length as f64 / chunk_length as f64;
+        self.set_chunk_sizes(chunk_sizes);
+    }
+
+    /// Distributes several files at once, each in its own swarm, instead of
+    /// a single file. The chunks of the files, in order, make up the chunks
+    /// of the distribution. A peer in several swarms shares its capacity
+    /// between them. This cannot be combined with live streaming or
+    /// playback.
+    pub fn set_swarms(&mut self, swarms: Vec<SwarmConfig>) {
+        assert!(self.live.is_none() && self.playback.is_none() && self.coding.is_none());
+        assert_eq!(
+            swarms.iter().map(|s| s.number_chunks).sum::<usize>(),
+            self.number_chunks
+        );
+        assert!(swarms
+            .iter()
+            .flat_map(|s| &s.peers)
+            .all(|p| *p >= self.number_seeds && *p < self.number_peers));
+        assert!((self.number_seeds..self.number_peers)
+            .all(|p| swarms.iter().any(|s| s.peers.contains(&p))));
+        self.swarms = swarms;
+    }
+}
+
+impl Chunk {
+    pub fn new(index: usize, size: usize, number_seeds: usize) -> Chunk {
+        Chunk {
+            index,
+            size,
+            number_blocks: 1,
+            completion_round: None,
+            first_copy_round: None,
+            number_possessing_peers: number_seeds,
+            number_ever_possessing_peers: number_seeds,
+            number_announcing_peers: number_seeds,
+        }
+    }
+}
+
+impl Peer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        index: usize,
+        file: &File,
+        is_seed: bool,
+        selfishness: Selfishness,
+        chunk_selector: Rc<dyn ChunkSelector>,
+        source_selector: Rc<dyn SourceSelector>,
+        upload_speed: usize,
+        download_speed: usize,
+    ) -> Peer {
+        assert!(!is_seed || selfishness == Selfishness::Altruistic);
+        Peer {
+            index,
+            selfishness,
+            chunk_selector,
+            source_selector,
+            upload_speed,
+            download_speed,
+            completion_round: if is_seed { Some(0) } else { None },
+            possessed_chunks: vec![is_seed; file.chunks.len()],
+            announced_chunks: vec![is_seed; file.chunks.len()],
+            number_uploads: 0,
+            join_round: 0,
+            join_time: 0.0,
+            departure_round: None,
+            playback: None,
+            missed_chunks: 0,
+            swarm_completion_rounds: vec![],
+            is_malicious: false,
+            adversary: Adversary::Honest,
+            is_active: true,
+            neighbours: None,
+            last_announce_round: None,
+            current_uploads: vec![],
+            current_downloads: vec![],
+            unchoked_peers: None,
+            optimistic_unchoke: None,
+            received_from: HashMap::new(),
+            sent_to: HashMap::new(),
+            received_blocks: HashMap::new(),
+            super_seeding_offers: None,
+            hash_failures: HashMap::new(),
+            banned_peers: vec![],
+            timed_out_sources: vec![],
+            sybil_peers: None,
+            reciprocated_rates: HashMap::new(),
+            coded_basis: None,
+            coded_requests: HashMap::new(),
+            partial_blocks: HashMap::new(),
+        }
+    }
+
+    fn allows_download(&self) -> bool {
+        self.is_active
+            && match self.selfishness {
+                Selfishness::Selfish => self.completion_round.is_none(),
+                Selfishness::Freerider => false,
+                _ => true,
+            }
+    }
+
+    fn available_capacity_for_block(
+        &self,
+        chunk_number: usize,
+        block_number: usize,
+        target_peer: usize,
+    ) -> usize {
+        // With network coding, a source can send a combination of any coded
+        // chunks that it has announced
+        let has_chunk = match self.coded_basis {
+            Some(_) => self.announced_chunks.contains(&true),
+            None => self.announced_chunks[chunk_number],
+        } && self.super_seeding_offers.as_ref().is_none_or(|offers| {
+            offers.get(&target_peer) == Some(&chunk_number)
+                || self.is_uploading(chunk_number, target_peer)
+        });
+        let is_unchoked =
+            self.is_unchoked(target_peer) || self.is_uploading(chunk_number, target_peer);
+        if self.allows_download()
+            && has_chunk
+            && is_unchoked
+            && self.is_neighbour(target_peer)
+            && !self.banned_peers.contains(&target_peer)
+            && self.uploads_to_entity(target_peer)
+        {
+            let other_uploads = self.current_uploads.iter().filter(|u| {
+                u.target_peer != target_peer
+                    || u.chunk_number != chunk_number
+                    || u.block_number != block_number
+            });
+            let used_capacity: usize = other_uploads.clone().map(|u| u.current_size).sum();
+            let available_capacity = self.upload_speed.saturating_sub(used_capacity);
+            match self.reciprocated_rate(target_peer) {
+                Some(rate) => {
+                    let used_rate: usize = other_uploads
+                        .filter(|u| u.target_peer == target_peer)
+                        .map(|u| u.current_size)
+                        .sum();
+                    cmp::min(available_capacity, rate.saturating_sub(used_rate))
+                }
+                None => available_capacity,
+            }
+        } else {
+            0
+        }
+    }
+
+    fn has_finished_seeding(
+        &self,
+        round_number: usize,
+        seeding_rounds: Option<usize>,
+        blocks_per_chunk: usize,
+    ) -> bool {
+        let Some(completion_round) = self.completion_round else {
+            return false;
+        };
+        let seeded_rounds = round_number - completion_round;
+        match self.selfishness {
+            Selfishness::SeedForRounds(rounds) => seeded_rounds >= rounds,
+            Selfishness::SeedUntilRatio(ratio) => {
+                self.number_uploads as f64
+                    >= ratio * (self.possessed_chunks.len() * blocks_per_chunk) as f64
+            }
+            _ => seeding_rounds.is_some_and(|r| seeded_rounds >= r),
+        }
+    }
+
+    pub fn is_neighbour(&self, peer: usize) -> bool {
+        match &self.neighbours {
+            Some(neighbours) => neighbours.binary_search(&peer).is_ok(),
+            None => true,
+        }
+    }
+
+    pub fn is_unchoked(&self, peer: usize) -> bool {
+        match &self.unchoked_peers {
+            Some(unchoked_peers) => {
+                self.optimistic_unchoke == Some(peer) || unchoked_peers.contains(&peer)
+            }
+            None => true,
+        }
+    }
+
+    fn record_transfer(&mut self, source_peer: usize, target_peer: usize, transfer_size: usize) {
+        if self.index == source_peer {
+            *self.sent_to.entry(target_peer).or_default() += transfer_size;
+        } else {
+            *self.received_from.entry(source_peer).or_default() += transfer_size;
+        }
+    }
+
+    fn rechoke<R: Rng + ?Sized>(
+        &mut self,
+        choking: &ChokingConfig,
+        rotate_optimistic_unchoke: bool,
+        interested_peers: &[usize],
+        rng: &mut R,
+    ) {
+        let neighbours = &self.neighbours;
+        let Some(unchoked_peers) = &mut self.unchoked_peers else {
+            return;
+        };
+        let transfers = if self.completion_round.is_some() {
+            &self.sent_to
+        } else {
+            &self.received_from
+        };
+        let is_neighbour = |p: &usize| {
+            neighbours
+                .as_ref()
+                .is_none_or(|n| n.binary_search(p).is_ok())
+        };
+        let mut reciprocating_peers: Vec<(usize, usize)> = transfers
+            .iter()
+            .filter(|(p, _)| interested_peers.binary_search(p).is_ok())
+            .map(|(p, t)| (*p, *t))
+            .collect();
+        reciprocating_peers.shuffle(rng);
+        if self.adversary == Adversary::Strategic && self.completion_round.is_none() {
+            // A strategic peer ranks the peers by what they give in return
+            // for what it sends them
+            let sent_to = &self.sent_to;
+            let return_rate = |(p, t): &(usize, usize)| {
+                *t as f64 / (sent_to.get(p).copied().unwrap_or(0) + 1) as f64
+            };
+            reciprocating_peers.sort_by(|a, b| return_rate(b).total_cmp(&return_rate(a)));
+        } else {
+            reciprocating_peers.sort_by_key(|(_, t)| cmp::Reverse(*t));
+        }
+        let regular_slots = choking.upload_slots - 1;
+        unchoked_peers.clear();
+        unchoked_peers.extend(
+            reciprocating_peers
+                .iter()
+                .map(|(p, _)| *p)
+                .take(regular_slots),
+        );
+        let choked_peers: Vec<usize> = interested_peers
+            .iter()
+            .copied()
+            .filter(|p| *p != self.index && is_neighbour(p) && !unchoked_peers.contains(p))
+            .collect();
+        let free_slots = regular_slots - unchoked_peers.len();
+        unchoked_peers.extend(choked_peers.choose_multiple(rng, free_slots));
+        let optimistic_unchoke_valid = self.optimistic_unchoke.is_some_and(|p| {
+            interested_peers.binary_search(&p).is_ok() && !unchoked_peers.contains(&p)
+        });
+        if rotate_optimistic_unchoke || !optimistic_unchoke_valid {
+            self.rotate_optimistic_unchoke(interested_peers, rng);
+        }
+        if self.adversary == Adversary::Strategic {
+            self.reciprocated_rates = self
+                .received_from
+                .iter()
+                .filter(|(_, t)| **t > 0)
+                .map(|(p, t)| (*p, t.div_ceil(choking.rechoke_interval)))
+                .collect();
+        }
+        self.received_from.clear();
+        self.sent_to.clear();
+    }
+
+    /// Gives the optimistic unchoke to a random interested peer that is
+    /// choked.
+    fn rotate_optimistic_unchoke<R: Rng + ?Sized>(
+        &mut self,
+        interested_peers: &[usize],
+        rng: &mut R,
+    ) {
+        let Some(unchoked_peers) = &self.unchoked_peers else {
+            return;
+        };
+        let choked_peers: Vec<usize> = interested_peers
+            .iter()
+            .copied()
+            .filter(|p| *p != self.index && self.is_neighbour(*p) && !unchoked_peers.contains(p))
+            .collect();
+        self.optimistic_unchoke = choked_peers.choose(rng).copied();
+    }
+
+    fn index_of_upload(
+        &self,
+        chunk_number: usize,
+        block_number: usize,
+        target_peer: usize,
+    ) -> Option<usize> {
+        self.current_uploads.iter().position(|u| {
+            u.chunk_number == chunk_number
+                && u.block_number == block_number
+                && u.target_peer == target_peer
+        })
+    }
+
+    fn is_uploading(&self, chunk_number: usize, target_peer: usize) -> bool {
+        self.current_uploads
+            .iter()
+            .any(|u| u.chunk_number == chunk_number && u.target_peer == target_peer)
+    }
+
+    fn is_downloading_block(&self, chunk_number: usize, block_number: usize) -> bool {
+        self.current_downloads
+            .iter()
+            .any(|d| d.chunk_number == chunk_number && d.block_number == block_number)
+    }
+
+    fn is_downloading_from(
+        &self,
+        chunk_number: usize,
+        block_number: usize,
+        source_peer: usize,
+    ) -> bool {
+        self.current_downloads.iter().any(|d| {
+            d.chunk_number == chunk_number
+                && d.block_number == block_number
+                && d.source_peer == source_peer
+        })
+    }
+
+    fn update_download(&mut self, download: Download) {
+        if let Some(index) = self.current_downloads.iter().position(|d| {
+            d.chunk_number == download.chunk_number
+                && d.block_number == download.block_number
+                && d.source_peer == download.source_peer
+        }) {
+            self.current_downloads[index] = download
+        } else {
+            self.current_downloads.push(download)
+        }
+    }
+
+    fn download(&mut self, download: Download) {
+        if let Some(index) = self.index_of_upload(
+            download.chunk_number,
+            download.block_number,
+            download.target_peer,
+        ) {
+            self.current_uploads[index] = download
+        } else {
+            self.current_uploads.push(download)
+        }
+    }
+
+    fn chunk_upload_finished(
+        &mut self,
+        chunk_number: usize,
+        block_number: usize,
+        target_peer: usize,
+    ) {
+        if let Some(index) = self.index_of_upload(chunk_number, block_number, target_peer) {
+            self.number_uploads += 1;
+            self.current_uploads.remove(index);
+        }
+    }
+
+    fn finish_download(
+        &mut self,
+        chunk_number: usize,
+        block_number: usize,
+        source_peer: usize,
+    ) -> Option<Download> {
+        let index = self.current_downloads.iter().position(|d| {
+            d.chunk_number == chunk_number
+                && d.block_number == block_number
+                && d.source_peer == source_peer
+        })?;
+        Some(self.current_downloads.remove(index))
+    }
+
+    /// Removes and returns the finished downloads. If several downloads of
+    /// the same block finished, only one of them is returned.
+    fn check_downloads_finished(&mut self) -> Vec<Download> {
+        let mut finished_downloads: Vec<Download> = vec![];
+        self.current_downloads.retain(|d| {
+            if d.downloaded_size >= d.size
+                && !finished_downloads
+                    .iter()
+                    .any(|f| f.chunk_number == d.chunk_number && f.block_number == d.block_number)
+            {
+                finished_downloads.push(*d);
+                false
+            } else {
+                true
+            }
+        });
+        finished_downloads
+    }
+}
+
+impl Distribution {
     pub fn new(config: &Config) -> Distribution {
-        let mut chunks = Vec::with_capacity(config.number_chunks);
-        for i in 0..config.number_chunks {
-            chunks.push(Chunk::new(i, config.number_seeds))
+        let mut speeds = config.peer_upload_speeds.clone();
+        speeds.extend(&config.peer_download_speeds);
+        let (mut chunk_size, mut speed_units) = Self::speed_units(&speeds);
+        if config.chunk_sizes.is_some() || config.blocks_per_chunk > 1 {
+            // Use finer units, so that chunks of other sizes and blocks can
+            // be represented closely enough
+            let scale = CHUNK_RESOLUTION.div_ceil(chunk_size);
+            chunk_size *= scale;
+            for speed in &mut speed_units {
+                *speed *= scale;
+            }
+        }
+        let number_chunks = match config.coding {
+            Some(Coding::Erasure { coded_chunks }) => coded_chunks,
+            _ => config.number_chunks,
+        };
+        let mut chunks = Vec::with_capacity(number_chunks);
+        for i in 0..number_chunks {
+            let size = match &config.chunk_sizes {
+                Some(chunk_sizes) => {
+                    cmp::max(1, (chunk_sizes[i] * chunk_size as f64).round() as usize)
+                }
+                None => chunk_size,
+            };
+            let mut chunk = Chunk::new(i, size, config.number_seeds);
+            chunk.number_blocks = cmp::min(config.blocks_per_chunk, size);
+            chunks.push(chunk)
         }
         let file = File { chunks };
-        let mut peers = Vec::with_capacity(config.number_peers);
+        let (upload_speeds, download_speeds) = speed_units.split_at(config.number_peers);
+        let mut peers: Vec<Peer> = Vec::with_capacity(config.number_peers);
         for i in 0..config.number_seeds {
             peers.push(Peer::new(
                 i,
                 &file,
                 true,
                 config.peer_selfishness[i],
-                config.peer_strategies[i],
-                config.peer_speeds[i],
+                config.peer_chunk_selectors[i].clone(),
+                config.peer_source_selectors[i].clone(),
+                upload_speeds[i],
+                download_speeds[i],
             ))
         }
         for i in config.number_seeds..config.number_peers {
@@ -383,15 +2007,77 @@ impl Distribution {
                 &file,
                 false,
                 config.peer_selfishness[i],
-                config.peer_strategies[i],
-                config.peer_speeds[i],
+                config.peer_chunk_selectors[i].clone(),
+                config.peer_source_selectors[i].clone(),
+                upload_speeds[i],
+                download_speeds[i],
             ))
         }
+        for (peer, is_malicious) in peers.iter_mut().zip(&config.peer_malicious) {
+            peer.is_malicious = *is_malicious;
+        }
+        Self::set_adversaries(&mut peers, &config.peer_adversaries);
+        assert!(
+            config.choking.is_some() || !config.peer_adversaries.contains(&Adversary::Strategic)
+        );
+        if config.coding == Some(Coding::Network) {
+            assert!(config.blocks_per_chunk == 1 && !config.super_seeding);
+            Self::set_coded_bases(&mut peers, config.number_seeds);
+        }
+        if config.choking.is_some() {
+            for peer in &mut peers {
+                peer.unchoked_peers = Some(vec![]);
+            }
+        }
+        if config.super_seeding {
+            for peer in &mut peers[..config.number_seeds] {
+                peer.super_seeding_offers = Some(HashMap::new());
+            }
+        }
+        if let Some(playback) = config.playback {
+            for peer in &mut peers[config.number_seeds..] {
+                peer.playback = Some(Playback::new(playback));
+            }
+        }
+        let swarms = Self::build_swarms(&config.swarms, config.number_seeds);
+        for peer in &mut peers {
+            peer.swarm_completion_rounds = vec![peer.completion_round; swarms.len()];
+        }
+        let swarm_rounds = vec![
+            SwarmRound {
+                completed_peers: config.number_seeds,
+                completed_chunks: 0,
+                exchanged_chunks: 0,
+            };
+            swarms.len()
+        ];
         Distribution {
             file,
             peers,
             number_seeds: config.number_seeds,
-            chunk_size: config.chunk_size,
+            swarms,
+            chunk_size,
+            has_chunk_sizes: config.chunk_sizes.is_some(),
+            blocks_per_chunk: config.blocks_per_chunk,
+            corruption_probability: config.corruption_probability,
+            ban_threshold: config.ban_threshold,
+            coding: config.coding,
+            original_chunks: config.number_chunks,
+            partial_progress: config.partial_progress,
+            choking: config.choking,
+            arrivals: config.arrivals.clone(),
+            departures: config.departures,
+            topology: config.topology.clone(),
+            tracker: config.tracker,
+            engine: config.engine,
+            announcement_delay: config.announcement_delay,
+            endgame_chunks: config.endgame_chunks,
+            live: config.live,
+            generated_chunks: 0,
+            expired_chunks: 0,
+            pending_announcements: VecDeque::new(),
+            rarity_drifts: vec![],
+            swarm_rounds,
         }
     }
 
@@ -400,142 +2086,874 @@ impl Distribution {
         observer.random_seed(random_seed);
         let mut rng = ChaCha8Rng::seed_from_u64(random_seed);
         observer.chunk_size(self.chunk_size);
+        self.schedule_arrivals(&mut rng);
+        self.build_overlay(&mut rng);
+        self.clear_peer_lists();
+        self.start_live_stream();
+        match self.engine {
+            Engine::Rounds => self.run_rounds(&mut rng, &observer),
+            Engine::Events { latency } => self.run_events(latency, &mut rng, &observer),
+        }
+    }
+
+    fn run_rounds<Obs: RunObserver>(&mut self, rng: &mut ChaCha8Rng, observer: &Obs) -> Vec<Round> {
         let mut rounds = vec![];
-        let mut current_round = Round {
-            completed_peers: self.number_seeds,
-            completed_chunks: 0,
-            exchanged_chunks: 0,
-            execution_time: Duration::from_secs(0),
-        };
+        let mut current_round = self.initial_round();
         rounds.push(current_round.clone());
         let mut shuffled_peers: Vec<usize> = (0..self.peers.len()).collect();
         let mut temporary_chunks: Vec<usize> = (0..self.file.chunks.len()).collect();
         let number_peers = self.peers.len();
-        while current_round.completed_peers < self.peers.len() {
+        let mut aborted_peers = 0;
+        while current_round.completed_peers + aborted_peers < self.peers.len() {
             observer.round_start(rounds.len());
             let start_time = Instant::now();
             let mut exchanged_chunks = 0;
             let mut completed_peers = 0;
             let mut completed_chunks = 0;
-            shuffled_peers[0..self.number_seeds].shuffle(&mut rng);
-            shuffled_peers[self.number_seeds..].shuffle(&mut rng);
-            temporary_chunks.sort_by_key(|c| self.file.chunks[*c].number_possessing_peers);
-            for peer_index in &shuffled_peers[self.number_seeds..number_peers] {
-                if self.peers[*peer_index].completion_round.is_some() {
-                    continue;
+            let mut left_peers = 0;
+            let mut joined_peers = 0;
+            let mut wasted_size = 0;
+            let mut corrupted_chunks = 0;
+            let mut bans = 0;
+            self.deliver_announcements(rounds.len());
+            for peer in &mut self.peers {
+                if !peer.is_active
+                    && peer.departure_round.is_none()
+                    && peer.join_round <= rounds.len()
+                {
+                    peer.is_active = true;
+                    observer.peer_joined(peer.index);
+                    joined_peers += 1;
                 }
-                if let Some(mut download) = self.peers[*peer_index].current_download {
-                    let desired_capacity = self.desired_download_capacity(
-                        download.chunk_number,
-                        download.source_peer,
-                        *peer_index,
-                    );
-                    let remaining_size = self.chunk_size - download.downloaded_size;
-                    let desired_size = cmp::min(desired_capacity, remaining_size);
-                    assert!(desired_size > 0);
-                    observer.chunk_transfer(
-                        download.chunk_number,
-                        desired_size,
-                        download.source_peer,
-                        download.target_peer,
-                    );
-                    download.current_size = desired_size;
-                    download.downloaded_size += desired_size;
-                    self.peers[*peer_index].current_download = Some(download);
-                    self.peers[download.source_peer].download(download);
+            }
+            completed_peers += self.advance_live_stream(rounds.len(), observer).len();
+            self.advance_playback(rounds.len());
+            if self.departures.abort_probability > 0.0 {
+                for peer_index in self.number_seeds..number_peers {
+                    let peer = &self.peers[peer_index];
+                    if peer.is_active
+                        && peer.completion_round.is_none()
+                        && rng.gen_bool(self.departures.abort_probability)
+                    {
+                        self.depart(peer_index, rounds.len(), observer);
+                        aborted_peers += 1;
+                        left_peers += 1;
+                    }
+                }
+            }
+            shuffled_peers[0..self.number_seeds].shuffle(rng);
+            shuffled_peers[self.number_seeds..].shuffle(rng);
+            for peer_index in &shuffled_peers {
+                if self.is_announce_due(*peer_index, rounds.len()) {
+                    self.announce_to_tracker(*peer_index, rounds.len(), rng, observer);
+                }
+            }
+            if let Some(choking) = self.choking {
+                self.update_choking(&choking, rounds.len() - 1, rng);
+            }
+            let (failed_downloads, dropped_size) =
+                self.abort_failed_downloads(|d| d.downloaded_size, observer);
+            let mut aborted_downloads = failed_downloads.len();
+            wasted_size += dropped_size;
+            if self.has_chunk_sizes {
+                // Ongoing downloads get their capacity anew in each round,
+                // except withheld ones, which keep the capacity reserved for
+                // them
+                for peer_index in self.number_seeds..number_peers {
+                    for download in self.peers[peer_index].current_downloads.clone() {
+                        if !self.is_withheld(&download) {
+                            self.transfer(Download {
+                                current_size: 0,
+                                ..download
+                            });
+                        }
+                    }
+                }
+            }
+            let mut download_capacities = vec![0; number_peers];
+            for peer_index in &shuffled_peers[self.number_seeds..number_peers] {
+                self.withdraw_timed_out_requests(*peer_index, rounds.len());
+                let peer = &self.peers[*peer_index];
+                if peer.completion_round.is_some() || !peer.is_active {
                     continue;
                 }
-                self.randomize_chunks(&mut rng, &mut temporary_chunks);
-                let peer_chunks: Box<dyn Iterator<Item = &usize>> =
-                    match self.peers[*peer_index].strategy {
-                        Strategy::RarestFirst => Box::new(temporary_chunks.iter()),
-                        Strategy::MostCommonFirst => Box::new(temporary_chunks.iter().rev()),
-                        Strategy::Uniform => Box::new(
-                            temporary_chunks.choose_multiple(&mut rng, temporary_chunks.len()),
-                        ),
-                    };
-                'chunk_search: for chunk_index in peer_chunks {
-                    if self.peers[*peer_index].possessed_chunks[*chunk_index] {
+                let mut download_capacity = peer.download_speed;
+                for mut download in peer.current_downloads.clone() {
+                    if self.is_withheld(&download) {
+                        download_capacity = download_capacity.saturating_sub(download.current_size);
                         continue;
                     }
-                    for shuffled_source_peer_index in
-                        (self.number_seeds..number_peers).chain(0..self.number_seeds)
-                    {
-                        let source_peer_index = shuffled_peers[shuffled_source_peer_index];
-                        let desired_capacity = self.desired_download_capacity(
-                            *chunk_index,
-                            source_peer_index,
+                    let upload_capacity = self.peers[download.source_peer]
+                        .available_capacity_for_block(
+                            download.chunk_number,
+                            download.block_number,
                             *peer_index,
                         );
-                        if desired_capacity == 0 {
-                            continue;
-                        }
+                    let remaining_size = download.size - download.downloaded_size;
+                    let desired_size =
+                        cmp::min(cmp::min(upload_capacity, download_capacity), remaining_size);
+                    if desired_size > 0 {
                         observer.chunk_transfer(
-                            *chunk_index,
-                            desired_capacity,
-                            source_peer_index,
-                            *peer_index,
+                            download.chunk_number,
+                            desired_size,
+                            download.source_peer,
+                            download.target_peer,
                         );
-                        exchanged_chunks += 1;
-                        let download = Download {
-                            chunk_number: *chunk_index,
-                            source_peer: source_peer_index,
-                            target_peer: *peer_index,
-                            downloaded_size: desired_capacity,
-                            current_size: desired_capacity,
-                        };
-                        self.peers[*peer_index].current_download = Some(download);
-                        self.peers[source_peer_index].download(download);
-                        break 'chunk_search;
                     }
+                    download.current_size = desired_size;
+                    download.downloaded_size += desired_size;
+                    download_capacity -= desired_size;
+                    self.transfer(download);
+                    self.record_transfer(&download, desired_size);
+                }
+                download_capacities[*peer_index] = download_capacity;
+                if !self.has_chunk_sizes {
+                    exchanged_chunks += self.start_new_downloads(
+                        *peer_index,
+                        download_capacity,
+                        &mut temporary_chunks,
+                        &shuffled_peers,
+                        rounds.len(),
+                        rng,
+                        observer,
+                    );
+                }
+            }
+            if self.has_chunk_sizes {
+                // Every ongoing download continues before any new download
+                // starts, so that new downloads, which chunks of other sizes
+                // leave capacity for, cannot take the source capacity that
+                // ongoing downloads need
+                for peer_index in &shuffled_peers[self.number_seeds..number_peers] {
+                    exchanged_chunks += self.start_new_downloads(
+                        *peer_index,
+                        download_capacities[*peer_index],
+                        &mut temporary_chunks,
+                        &shuffled_peers,
+                        rounds.len(),
+                        rng,
+                        observer,
+                    );
                 }
             }
             let mut finished_uploads: Vec<Download> = vec![];
-            for peer in &mut self.peers {
-                if let Some(download) = peer.check_chunk_download_finished(self.chunk_size) {
-                    let chunk = &mut self.file.chunks[download.chunk_number];
-                    chunk.number_possessing_peers += 1;
-                    if chunk.number_possessing_peers == number_peers {
-                        observer.chunk_completed(chunk.index);
-                        chunk.completion_round = Some(rounds.len());
+            for peer_index in 0..number_peers {
+                let finished_downloads = self.peers[peer_index].check_downloads_finished();
+                if finished_downloads.is_empty() {
+                    continue;
+                }
+                for download in finished_downloads {
+                    finished_uploads.push(download);
+                    for cancelled in self.cancel_downloads(
+                        peer_index,
+                        download.chunk_number,
+                        Some(download.block_number),
+                    ) {
+                        wasted_size += cancelled.downloaded_size;
+                    }
+                    let chunk = &self.file.chunks[download.chunk_number];
+                    let Some(block_sources) =
+                        self.peers[peer_index].receive_block(chunk, &download)
+                    else {
+                        continue;
+                    };
+                    if !self.passes_hash_check(&block_sources, rng) {
+                        corrupted_chunks += 1;
+                        wasted_size += chunk.size;
+                        let (new_bans, stopped_downloads) = self.record_hash_failure(
+                            peer_index,
+                            download.chunk_number,
+                            &block_sources,
+                            observer,
+                        );
+                        bans += new_bans;
+                        for stopped in stopped_downloads {
+                            wasted_size += stopped.downloaded_size;
+                        }
+                        continue;
+                    }
+                    if !self.receive_coded_chunk(&download) {
+                        continue;
+                    }
+                    self.peers[peer_index].possessed_chunks[download.chunk_number] = true;
+                    if self.chunk_possessed(download.chunk_number, rounds.len(), observer) {
                         completed_chunks += 1;
                     }
-                    if peer.possessed_chunks.iter().all(|c| *c) {
-                        observer.peer_completed(peer.index);
-                        peer.completion_round = Some(rounds.len());
-                        completed_peers += 1;
+                    if self.announcement_delay == 0 {
+                        self.announce(peer_index, download.chunk_number);
+                    } else {
+                        self.pending_announcements.push_back((
+                            rounds.len() + self.announcement_delay,
+                            peer_index,
+                            download.chunk_number,
+                        ));
+                    }
+                }
+                if self.check_peer_completed(peer_index, rounds.len(), observer) {
+                    completed_peers += 1;
+                    for cancelled in self.cancel_unneeded_downloads(peer_index) {
+                        wasted_size += cancelled.downloaded_size;
                     }
-                    finished_uploads.push(download)
                 }
             }
+            let mut seed_uploads = 0;
             for upload in finished_uploads {
-                self.peers[upload.source_peer]
-                    .chunk_upload_finished(upload.chunk_number, upload.target_peer)
+                if upload.source_peer < self.number_seeds {
+                    seed_uploads += 1;
+                }
+                self.peers[upload.source_peer].chunk_upload_finished(
+                    upload.chunk_number,
+                    upload.block_number,
+                    upload.target_peer,
+                )
+            }
+            for peer_index in self.number_seeds..number_peers {
+                let peer = &self.peers[peer_index];
+                if peer.is_active
+                    && peer.has_finished_seeding(
+                        rounds.len(),
+                        self.departures.seeding_rounds,
+                        self.blocks_per_chunk,
+                    )
+                {
+                    self.depart(peer_index, rounds.len(), observer);
+                    left_peers += 1;
+                }
             }
+            let (failed_downloads, dropped_size) =
+                self.abort_failed_downloads(|d| d.downloaded_size, observer);
+            aborted_downloads += failed_downloads.len();
+            wasted_size += dropped_size;
             current_round.completed_peers += completed_peers;
             current_round.completed_chunks += completed_chunks;
             current_round.exchanged_chunks = exchanged_chunks;
+            current_round.joined_peers = joined_peers;
+            current_round.left_peers = left_peers;
+            current_round.rarity_drift = self.take_rarity_drift();
+            current_round.wasted_chunks = wasted_size as f64 / self.chunk_size as f64;
+            current_round.seed_uploads = seed_uploads;
+            current_round.corrupted_chunks = corrupted_chunks;
+            current_round.bans = bans;
+            current_round.aborted_downloads = aborted_downloads;
+            current_round.swarms = self.take_swarm_rounds();
             current_round.execution_time = start_time.elapsed();
             observer.round_end(rounds.len(), &current_round);
             rounds.push(current_round.clone());
             current_round = Round::new(&current_round);
+            if !self.can_progress() {
+                break;
+            }
         }
         rounds
     }
 
-    fn randomize_chunks<R: Rng + ?Sized>(&self, rng: &mut R, chunks: &mut Vec<usize>) {
+    fn initial_round(&self) -> Round {
+        Round {
+            completed_peers: self.number_seeds,
+            completed_chunks: 0,
+            exchanged_chunks: 0,
+            joined_peers: 0,
+            left_peers: 0,
+            rarity_drift: 0.0,
+            wasted_chunks: 0.0,
+            seed_uploads: 0,
+            corrupted_chunks: 0,
+            bans: 0,
+            aborted_downloads: 0,
+            swarms: self.swarm_rounds.clone(),
+            execution_time: Duration::from_secs(0),
+        }
+    }
+
+    /// Starts new downloads for a peer in a round with the download capacity
+    /// that its ongoing downloads left, and returns how many it started.
+    #[allow(clippy::too_many_arguments)]
+    fn start_new_downloads<R: Rng, Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        download_capacity: usize,
+        temporary_chunks: &mut [usize],
+        shuffled_peers: &[usize],
+        round_number: usize,
+        rng: &mut R,
+        observer: &Obs,
+    ) -> usize {
+        if download_capacity == 0 {
+            return 0;
+        }
+        let started_downloads = self.start_downloads(
+            peer_index,
+            download_capacity,
+            temporary_chunks,
+            shuffled_peers,
+            round_number,
+            rng,
+            observer,
+        );
+        for download in &started_downloads {
+            self.record_transfer(download, download.current_size);
+            self.record_swarm_exchange(download.chunk_number);
+        }
+        started_downloads.len()
+    }
+
+    /// Selects new chunks for a peer to download and starts downloading
+    /// them, until the peer has no download capacity left or no suitable
+    /// source is found for any chunk. Returns the started downloads.
+    #[allow(clippy::too_many_arguments)]
+    fn start_downloads<R: Rng, Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        mut download_capacity: usize,
+        temporary_chunks: &mut [usize],
+        shuffled_peers: &[usize],
+        round_number: usize,
+        rng: &mut R,
+        observer: &Obs,
+    ) -> Vec<Download> {
+        if self.coding == Some(Coding::Network) {
+            return self.start_coded_downloads(
+                peer_index,
+                download_capacity,
+                shuffled_peers,
+                round_number,
+                rng,
+                observer,
+            );
+        }
+        let mut started_downloads = vec![];
+        self.update_super_seeding_offers(peer_index, rng);
+        let in_endgame = self.is_in_endgame(peer_index);
+        let availability = self.chunk_availability(peer_index);
+        temporary_chunks.sort_by_key(|c| availability[*c]);
+        Self::randomize_chunks(rng, temporary_chunks, &availability);
+        let peer = &self.peers[peer_index];
+        let context = ChunkSelectionContext {
+            peer,
+            peers: &self.peers,
+            file: &self.file,
+            chunks_by_rarity: temporary_chunks,
+            round_number,
+        };
+        let mut peer_chunks = peer.chunk_selector.select_chunks(&context, rng);
+        self.record_rarity_drift(peer_index, &peer_chunks);
+        // Chunks that are partly downloaded are completed first, so that
+        // they can be shared sooner
+        let received_blocks = &self.peers[peer_index].received_blocks;
+        peer_chunks.sort_by_key(|c| !received_blocks.contains_key(c));
+        'chunks: for chunk_index in peer_chunks {
+            if self.peers[peer_index].possessed_chunks[chunk_index]
+                || chunk_index < self.expired_chunks
+                || !self.wants_chunk(peer_index, chunk_index)
+            {
+                continue;
+            }
+            // The blocks of a chunk are downloaded from different sources,
+            // and in endgame mode, one more source is added for a block
+            // that is already being downloaded
+            let mut is_started = false;
+            while let Some(block_number) =
+                self.peers[peer_index].next_block(&self.file.chunks[chunk_index], in_endgame)
+            {
+                let peer = &self.peers[peer_index];
+                let is_duplicate = peer.is_downloading_block(chunk_index, block_number);
+                if is_duplicate && is_started {
+                    break;
+                }
+                let context = SourceSelectionContext {
+                    chunk_number: chunk_index,
+                    block_number,
+                    target_peer: peer_index,
+                    peers: &self.peers,
+                    number_seeds: self.number_seeds,
+                    shuffled_peers,
+                };
+                let Some(source_peer_index) = peer.source_selector.select_source(&context, rng)
+                else {
+                    break;
+                };
+                if peer.is_downloading_from(chunk_index, block_number, source_peer_index) {
+                    break;
+                }
+                let Some(download) = self.start_download(
+                    chunk_index,
+                    block_number,
+                    source_peer_index,
+                    peer_index,
+                    download_capacity,
+                    round_number,
+                    observer,
+                ) else {
+                    break;
+                };
+                download_capacity -= download.current_size;
+                started_downloads.push(download);
+                is_started = true;
+                if download_capacity == 0 {
+                    break 'chunks;
+                }
+                if is_duplicate {
+                    break;
+                }
+            }
+        }
+        started_downloads
+    }
+
+    /// Starts downloading a block from a source with as much capacity as
+    /// both peers have available, and returns the download, or `None` if
+    /// there is no capacity.
+    #[allow(clippy::too_many_arguments)]
+    fn start_download<Obs: RunObserver>(
+        &mut self,
+        chunk_number: usize,
+        block_number: usize,
+        source_peer: usize,
+        target_peer: usize,
+        download_capacity: usize,
+        round_number: usize,
+        observer: &Obs,
+    ) -> Option<Download> {
+        let chunk = &self.file.chunks[chunk_number];
+        let size = self.peers[target_peer].remaining_block_size(chunk, block_number);
+        let upload_capacity = self.peers[source_peer].available_capacity_for_block(
+            chunk_number,
+            block_number,
+            target_peer,
+        );
+        let mut desired_capacity = cmp::min(upload_capacity, download_capacity);
+        if self.engine == Engine::Rounds {
+            // The capacity is the amount to transfer in the first round
+            // rather than a rate, so it cannot exceed the block
+            desired_capacity = cmp::min(desired_capacity, size);
+        }
+        if desired_capacity == 0 {
+            return None;
+        }
+        self.peers[target_peer]
+            .partial_blocks
+            .remove(&(chunk_number, block_number));
+        let mut download = Download {
+            chunk_number,
+            block_number,
+            size,
+            source_peer,
+            target_peer,
+            downloaded_size: desired_capacity,
+            current_size: desired_capacity,
+            request_round: round_number,
+        };
+        if self.is_withheld(&download) {
+            // The source accepts the request and the capacity is reserved
+            // for it, but no data arrives
+            download.downloaded_size = 0;
+        } else {
+            observer.chunk_transfer(chunk_number, desired_capacity, source_peer, target_peer);
+        }
+        self.transfer(download);
+        Some(download)
+    }
+
+    /// Returns whether a peer is missing few enough chunks to be in endgame
+    /// mode.
+    fn is_in_endgame(&self, peer_index: usize) -> bool {
+        self.endgame_chunks.is_some_and(|endgame_chunks| {
+            let missing_chunks = self.peers[peer_index]
+                .possessed_chunks
+                .iter()
+                .enumerate()
+                .filter(|(c, possessed)| !**possessed && self.wants_chunk(peer_index, *c))
+                .count();
+            missing_chunks.saturating_sub(self.redundant_chunks()) <= endgame_chunks
+        })
+    }
+
+    /// Cancels the ongoing downloads of a chunk by a peer, or only of one
+    /// block of it, which are either duplicates of a finished download or
+    /// no longer needed, and returns the cancelled downloads.
+    fn cancel_downloads(
+        &mut self,
+        peer_index: usize,
+        chunk_number: usize,
+        block_number: Option<usize>,
+    ) -> Vec<Download> {
+        let peer = &mut self.peers[peer_index];
+        let mut cancelled_downloads = vec![];
+        peer.current_downloads.retain(|d| {
+            if d.chunk_number == chunk_number && block_number.is_none_or(|b| d.block_number == b) {
+                cancelled_downloads.push(*d);
+                false
+            } else {
+                true
+            }
+        });
+        for download in &cancelled_downloads {
+            let source_peer = &mut self.peers[download.source_peer];
+            if let Some(index) =
+                source_peer.index_of_upload(chunk_number, download.block_number, peer_index)
+            {
+                source_peer.current_uploads.remove(index);
+            }
+        }
+        cancelled_downloads
+    }
+
+    /// Records that one more peer possesses a chunk, and returns whether
+    /// every peer in its swarm has now had the chunk.
+    fn chunk_possessed<Obs: RunObserver>(
+        &mut self,
+        chunk_number: usize,
+        round_number: usize,
+        observer: &Obs,
+    ) -> bool {
+        let number_wanting_peers = self.number_wanting_peers(chunk_number);
+        let chunk = &mut self.file.chunks[chunk_number];
+        chunk.number_possessing_peers += 1;
+        chunk.number_ever_possessing_peers += 1;
+        if chunk.first_copy_round.is_none() {
+            chunk.first_copy_round = Some(round_number);
+        }
+        if chunk.number_ever_possessing_peers == number_wanting_peers {
+            observer.chunk_completed(chunk.index);
+            chunk.completion_round = Some(round_number);
+            self.record_swarm_chunk_completed(chunk_number);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn check_peer_completed<Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        round_number: usize,
+        observer: &Obs,
+    ) -> bool {
+        self.check_swarms_completed(peer_index, round_number, observer);
+        let peer = &self.peers[peer_index];
+        let missing_chunks = (self.expired_chunks..peer.possessed_chunks.len())
+            .filter(|c| !peer.possessed_chunks[*c] && self.wants_chunk(peer_index, *c))
+            .count();
+        // With erasure coding, the file can be decoded without the
+        // redundant chunks
+        let has_all_chunks = missing_chunks <= self.redundant_chunks();
+        let peer = &mut self.peers[peer_index];
+        if peer.completion_round.is_none() && has_all_chunks {
+            observer.peer_completed(peer.index);
+            peer.completion_round = Some(round_number);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Converts speeds to an integer number of units transferred per round,
+    /// and returns the chunk size in the same units. A round is the time it
+    /// takes to transfer one chunk at the fastest speed.
+    ///
+    /// If all speeds are integers and their least common multiple, after
+    /// dividing by their greatest common divisor, is small enough, that is
+    /// used as the chunk size, so that every speed is represented exactly.
+    /// Otherwise, the speeds are rounded to the nearest unit in a fixed
+    /// scale.
+    fn speed_units(speeds: &[f64]) -> (usize, Vec<usize>) {
+        if speeds.iter().all(|s| s.fract() == 0.0) {
+            let integer_speeds: Vec<usize> = speeds.iter().map(|s| *s as usize).collect();
+            let speed_gcd = integer_speeds.iter().fold(0, |g, s| gcd(g, *s));
+            let integer_speeds: Vec<usize> = integer_speeds.iter().map(|s| s / speed_gcd).collect();
+            let chunk_size = integer_speeds.iter().try_fold(1, |l, s| {
+                Some(lcm(l, *s)).filter(|l| *l <= MAX_EXACT_CHUNK_SIZE)
+            });
+            if let Some(chunk_size) = chunk_size {
+                let max_speed = integer_speeds.iter().copied().max().unwrap_or(chunk_size);
+                let scale = chunk_size / max_speed;
+                return (
+                    chunk_size,
+                    integer_speeds.iter().map(|s| s * scale).collect(),
+                );
+            }
+        }
+        let max_speed = speeds.iter().copied().fold(0.0, f64::max);
+        let units = speeds
+            .iter()
+            .map(|s| {
+                cmp::max(
+                    1,
+                    (s / max_speed * CHUNK_RESOLUTION as f64).round() as usize,
+                )
+            })
+            .collect();
+        (CHUNK_RESOLUTION, units)
+    }
+
+    fn schedule_arrivals<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let non_seeds = &mut self.peers[self.number_seeds..];
+        match &self.arrivals {
+            ArrivalSchedule::FlashCrowd => {
+                for peer in non_seeds.iter_mut() {
+                    peer.join_round = 0;
+                }
+            }
+            ArrivalSchedule::Poisson { rate } => {
+                let mut arrival_time = 0.0;
+                for peer in non_seeds.iter_mut() {
+                    arrival_time -= (1.0 - rng.gen::<f64>()).ln() / rate;
+                    peer.join_round = arrival_time.ceil() as usize;
+                    peer.join_time = arrival_time;
+                }
+            }
+            ArrivalSchedule::Explicit(join_rounds) => {
+                for (i, peer) in non_seeds.iter_mut().enumerate() {
+                    peer.join_round = join_rounds.get(i).copied().unwrap_or(0);
+                }
+            }
+        }
+        for peer in non_seeds {
+            if !matches!(self.arrivals, ArrivalSchedule::Poisson { .. }) {
+                peer.join_time = peer.join_round as f64;
+            }
+            peer.is_active = peer.join_round == 0;
+        }
+    }
+
+    /// Makes a peer leave the distribution, and returns the downloads that
+    /// it dropped.
+    fn depart<Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        round_number: usize,
+        observer: &Obs,
+    ) -> Vec<Download> {
+        let peer = &mut self.peers[peer_index];
+        peer.is_active = false;
+        peer.departure_round = Some(round_number);
+        let downloads: Vec<Download> = peer.current_downloads.drain(..).collect();
+        for (chunk, possessed) in self.file.chunks.iter_mut().zip(&peer.possessed_chunks) {
+            if *possessed {
+                chunk.number_possessing_peers -= 1;
+            }
+        }
+        for (chunk, announced) in self.file.chunks.iter_mut().zip(&peer.announced_chunks) {
+            if *announced {
+                chunk.number_announcing_peers -= 1;
+            }
+        }
+        for download in &downloads {
+            let source_peer = &mut self.peers[download.source_peer];
+            if let Some(index) = source_peer.index_of_upload(
+                download.chunk_number,
+                download.block_number,
+                peer_index,
+            ) {
+                source_peer.current_uploads.remove(index);
+            }
+        }
+        // The uploads of the peer are aborted by the engine, which knows
+        // how far they got
+        observer.peer_left(peer_index);
+        downloads
+    }
+
+    fn transfer(&mut self, download: Download) {
+        self.peers[download.target_peer].update_download(download);
+        self.peers[download.source_peer].download(download);
+    }
+
+    fn record_transfer(&mut self, download: &Download, transfer_size: usize) {
+        if self.choking.is_some() {
+            for peer in [download.source_peer, download.target_peer] {
+                self.peers[peer].record_transfer(
+                    download.source_peer,
+                    download.target_peer,
+                    transfer_size,
+                );
+            }
+        }
+    }
+
+    /// Rechokes every `rechoke_interval` rounds, and rotates the optimistic
+    /// unchokes every `optimistic_unchoke_interval` rounds, independently
+    /// of each other.
+    fn update_choking<R: Rng + ?Sized>(
+        &mut self,
+        choking: &ChokingConfig,
+        round_index: usize,
+        rng: &mut R,
+    ) {
+        let rotate_optimistic_unchoke =
+            round_index.is_multiple_of(choking.optimistic_unchoke_interval);
+        if round_index.is_multiple_of(choking.rechoke_interval) {
+            self.rechoke(choking, rotate_optimistic_unchoke, rng);
+        } else if rotate_optimistic_unchoke {
+            let interested_peers = self.interested_peers();
+            for peer in &mut self.peers {
+                peer.rotate_optimistic_unchoke(&interested_peers, rng);
+            }
+        }
+    }
+
+    fn rechoke<R: Rng + ?Sized>(
+        &mut self,
+        choking: &ChokingConfig,
+        rotate_optimistic_unchoke: bool,
+        rng: &mut R,
+    ) {
+        let interested_peers = self.interested_peers();
+        for peer in &mut self.peers {
+            peer.rechoke(choking, rotate_optimistic_unchoke, &interested_peers, rng);
+        }
+    }
+
+    fn interested_peers(&self) -> Vec<usize> {
+        self.peers
+            .iter()
+            .filter(|p| p.is_active && p.completion_round.is_none())
+            .map(|p| p.index)
+            .collect()
+    }
+
+    /// Makes a chunk that a peer has downloaded known to the other peers,
+    /// unless the peer has already left.
+    fn announce(&mut self, peer_index: usize, chunk_number: usize) {
+        let peer = &mut self.peers[peer_index];
+        if !peer.is_active || peer.announced_chunks[chunk_number] {
+            return;
+        }
+        peer.announced_chunks[chunk_number] = true;
+        self.file.chunks[chunk_number].number_announcing_peers += 1;
+    }
+
+    /// Announces the pending chunks whose announcement is due before the
+    /// given round starts.
+    fn deliver_announcements(&mut self, round_number: usize) {
+        while let Some(&(due_round, peer_index, chunk_number)) = self.pending_announcements.front()
+        {
+            if due_round >= round_number {
+                break;
+            }
+            self.pending_announcements.pop_front();
+            self.announce(peer_index, chunk_number);
+        }
+    }
+
+    /// The number of peers possessing each chunk, as known to the given
+    /// peer. With an overlay topology, only active neighbours are counted.
+    fn chunk_availability(&self, peer_index: usize) -> Vec<usize> {
+        match &self.peers[peer_index].neighbours {
+            Some(neighbours) => {
+                let mut availability = vec![0; self.file.chunks.len()];
+                for neighbour in neighbours {
+                    let neighbour = &self.peers[*neighbour];
+                    if !neighbour.is_active {
+                        continue;
+                    }
+                    for (count, possessed) in
+                        availability.iter_mut().zip(&neighbour.announced_chunks)
+                    {
+                        if *possessed {
+                            *count += 1;
+                        }
+                    }
+                }
+                availability
+            }
+            None => self
+                .file
+                .chunks
+                .iter()
+                .map(|c| c.number_announcing_peers)
+                .collect(),
+        }
+    }
+
+    /// Compares the order in which a peer selected the chunks it does not
+    /// possess to the same chunks ordered by their actual rarity. The drift
+    /// is the average difference in the fraction of active peers possessing
+    /// the chunks at the same position in the two orders.
+    fn record_rarity_drift(&mut self, peer_index: usize, selected_chunks: &[usize]) {
+        let peer = &self.peers[peer_index];
+        let availability = |c: &usize| self.file.chunks[*c].number_possessing_peers;
+        let selected_availability: Vec<usize> = selected_chunks
+            .iter()
+            .filter(|c| !peer.possessed_chunks[**c] && self.wants_chunk(peer_index, **c))
+            .map(availability)
+            .collect();
+        if selected_availability.is_empty() {
+            return;
+        }
+        let mut actual_availability = selected_availability.clone();
+        actual_availability.sort();
+        let number_active_peers = self.peers.iter().filter(|p| p.is_active).count();
+        let total_difference: usize = selected_availability
+            .iter()
+            .zip(&actual_availability)
+            .map(|(s, a)| s.abs_diff(*a))
+            .sum();
+        self.rarity_drifts.push(
+            total_difference as f64 / (selected_availability.len() * number_active_peers) as f64,
+        );
+    }
+
+    /// Returns the average rarity drift of the chunk selections since the
+    /// previous call.
+    fn take_rarity_drift(&mut self) -> f64 {
+        if self.rarity_drifts.is_empty() {
+            return 0.0;
+        }
+        let drift = self.rarity_drifts.iter().sum::<f64>() / self.rarity_drifts.len() as f64;
+        self.rarity_drifts.clear();
+        drift
+    }
+
+    /// Returns whether any peer can still get a chunk it does not have.
+    /// This is false when the remaining peers are cut off from all chunks
+    /// they need, which can happen with an overlay topology. With a tracker,
+    /// a peer can still learn about any active peer. A live stream can
+    /// progress until all of its chunks have fallen out of the window.
+    fn can_progress(&self) -> bool {
+        let non_seeds = &self.peers[self.number_seeds..];
+        let is_waiting = |p: &Peer| p.completion_round.is_none() && p.departure_round.is_none();
+        if non_seeds.iter().any(|p| !p.current_downloads.is_empty())
+            || non_seeds.iter().any(|p| !p.is_active && is_waiting(p))
+            || (self.departures.abort_probability > 0.0 && non_seeds.iter().any(is_waiting))
+            || (self.live.is_some() && self.expired_chunks < self.file.chunks.len())
+        {
+            return true;
+        }
+        non_seeds.iter().filter(|p| is_waiting(p)).any(|peer| {
+            self.peers.iter().any(|source| {
+                source.index != peer.index
+                    && source.allows_download()
+                    && source.adversary != Adversary::Withholding
+                    && !source.banned_peers.contains(&peer.index)
+                    && (self.tracker.is_some() || source.is_neighbour(peer.index))
+                    && match self.coding {
+                        Some(Coding::Network) => self.has_innovative_chunks(source, peer),
+                        _ => source
+                            .possessed_chunks
+                            .iter()
+                            .zip(&peer.possessed_chunks)
+                            .enumerate()
+                            .skip(self.expired_chunks)
+                            .any(|(c, (s, p))| *s && !*p && self.wants_chunk(peer.index, c)),
+                    }
+            })
+        })
+    }
+
+    fn randomize_chunks<R: Rng + ?Sized>(
+        rng: &mut R,
+        chunks: &mut [usize],
+        availability: &[usize],
+    ) {
         let mut i = 0;
         while i < chunks.len() - 1 {
-            let chunk = &self.file.chunks[chunks[i]];
-            let next_chunk = &self.file.chunks[chunks[i + 1]];
-            if chunk.number_possessing_peers != next_chunk.number_possessing_peers {
+            let count = availability[chunks[i]];
+            if count != availability[chunks[i + 1]] {
                 i += 1
             } else {
                 let mut j = i + 2;
-                while j < chunks.len()
-                    && self.file.chunks[chunks[j]].number_possessing_peers
-                        == chunk.number_possessing_peers
-                {
+                while j < chunks.len() && availability[chunks[j]] == count {
                     j += 1
                 }
                 chunks[i..j].shuffle(rng);
@@ -543,18 +2961,6 @@ impl Distribution {
             }
         }
     }
-
-    fn desired_download_capacity(
-        &self,
-        chunk_number: usize,
-        source_peer: usize,
-        target_peer: usize,
-    ) -> usize {
-        let upload_capacity =
-            self.peers[source_peer].available_capacity_for_chunk(chunk_number, target_peer);
-        let target_speed = self.peers[target_peer].speed;
-        cmp::min(target_speed, upload_capacity)
-    }
 }
 
 impl Round {
@@ -563,6 +2969,15 @@ impl Round {
             completed_peers: previous_round.completed_peers,
             completed_chunks: previous_round.completed_chunks,
             exchanged_chunks: 0,
+            joined_peers: 0,
+            left_peers: 0,
+            rarity_drift: 0.0,
+            wasted_chunks: 0.0,
+            seed_uploads: 0,
+            corrupted_chunks: 0,
+            bans: 0,
+            aborted_downloads: 0,
+            swarms: previous_round.swarms.clone(),
             execution_time: Duration::from_secs(0),
         }
     }
@@ -589,12 +3004,35 @@ impl RunObserver for DebugRunObserver {
     ) {
         println!("Transfer size {transfer_size:?} of chunk {chunk_number:?} from {source_peer:?} to {target_peer:?}");
     }
+    fn peer_joined(&self, peer: usize) {
+        println!("Peer {peer:?} joined");
+    }
+    fn peer_left(&self, peer: usize) {
+        println!("Peer {peer:?} left");
+    }
     fn peer_completed(&self, peer: usize) {
         println!("Peer {peer:?} completed");
     }
+    fn swarm_completed(&self, peer: usize, swarm: usize) {
+        println!("Peer {peer:?} completed swarm {swarm:?}");
+    }
+    fn tracker_announce(&self, peer: usize, new_peers: usize) {
+        println!("Peer {peer:?} announced to tracker and got {new_peers:?} new peers");
+    }
     fn chunk_completed(&self, chunk_number: usize) {
         println!("Chunk {chunk_number:?} fully distributed");
     }
+    fn chunk_corrupted(&self, peer: usize, chunk_number: usize) {
+        println!("Peer {peer:?} got chunk {chunk_number:?} with bad data");
+    }
+    fn peer_banned(&self, peer: usize, banned_peer: usize) {
+        println!("Peer {peer:?} banned {banned_peer:?}");
+    }
+    fn download_aborted(&self, chunk_number: usize, source_peer: usize, target_peer: usize) {
+        println!(
+            "Download of chunk {chunk_number:?} from {source_peer:?} to {target_peer:?} aborted"
+        );
+    }
     fn round_end(&self, round_number: usize, round: &Round) {
         println!(
             "End round {:?} time {:?}",
@@ -611,3 +3049,43 @@ impl RunObserver for SummaryRunObserver {
         println!("Round {round_number:?}: {round:?}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One seed and two peers of equal speed, where the seed can upload
+    /// only one of the two chunks per round.
+    pub(crate) fn trivial_config() -> Config {
+        Config::from_counts(
+            2,
+            3,
+            1,
+            SpeedTiers::new(1, 1, 1),
+            0,
+            0,
+            Strategy::RarestFirst,
+        )
+    }
+
+    pub(crate) fn sorted_completion_rounds(distribution: &Distribution) -> Vec<Option<usize>> {
+        let mut completion_rounds: Vec<Option<usize>> = distribution
+            .peers
+            .iter()
+            .map(|p| p.completion_round)
+            .collect();
+        completion_rounds.sort();
+        completion_rounds
+    }
+
+    #[test]
+    fn rounds_complete_every_peer() {
+        let mut distribution = Distribution::new(&trivial_config());
+        let rounds = distribution.run(Some(1), EmptyRunObserver);
+        assert_eq!(rounds.len() - 1, 3);
+        assert_eq!(
+            sorted_completion_rounds(&distribution),
+            [Some(0), Some(2), Some(3)]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{Distribution, Download, LiveConfig, Peer, RunObserver};
+
+impl LiveConfig {
+    /// The time at which the seeds generate a chunk.
+    pub(crate) fn generation_time(&self, chunk_number: usize) -> f64 {
+        (chunk_number * self.chunk_interval) as f64
+    }
+
+    /// The time at which a chunk falls out of the window, which is when the
+    /// chunk `window` chunks later is generated.
+    pub(crate) fn expiry_time(&self, chunk_number: usize) -> f64 {
+        self.generation_time(chunk_number + self.window)
+    }
+}
+
+impl Peer {
+    /// The fraction of the chunks in the live stream that the peer
+    /// downloaded before they fell out of the window, among the chunks it
+    /// downloaded or missed, or `None` if it has neither downloaded nor
+    /// missed any chunks.
+    pub fn continuity(&self) -> Option<f64> {
+        let downloaded_chunks = self.possessed_chunks.iter().filter(|c| **c).count();
+        let relevant_chunks = downloaded_chunks + self.missed_chunks;
+        if relevant_chunks == 0 {
+            None
+        } else {
+            Some(downloaded_chunks as f64 / relevant_chunks as f64)
+        }
+    }
+}
+
+impl Distribution {
+    /// Removes the chunks from the seeds in live mode, so that they are
+    /// only available once generated.
+    pub(crate) fn start_live_stream(&mut self) {
+        if self.live.is_none() {
+            return;
+        }
+        self.generated_chunks = 0;
+        self.expired_chunks = 0;
+        for peer in &mut self.peers[..self.number_seeds] {
+            peer.possessed_chunks.fill(false);
+            peer.announced_chunks.fill(false);
+        }
+        for chunk in &mut self.file.chunks {
+            chunk.number_possessing_peers = 0;
+            chunk.number_ever_possessing_peers = 0;
+            chunk.number_announcing_peers = 0;
+        }
+    }
+
+    /// Generates and expires the chunks that are due by the start of the
+    /// given round, and returns the peers that completed as a result.
+    pub(crate) fn advance_live_stream<Obs: RunObserver>(
+        &mut self,
+        round_number: usize,
+        observer: &Obs,
+    ) -> Vec<usize> {
+        let Some(live) = self.live else {
+            return vec![];
+        };
+        let time = (round_number - 1) as f64;
+        let number_chunks = self.file.chunks.len();
+        while self.generated_chunks < number_chunks
+            && live.generation_time(self.generated_chunks) <= time
+        {
+            self.generate_chunk(self.generated_chunks);
+        }
+        let mut completed_peers = vec![];
+        while self.expired_chunks < number_chunks && live.expiry_time(self.expired_chunks) <= time {
+            let (_, completed) = self.expire_chunk(self.expired_chunks, round_number, observer);
+            completed_peers.extend(completed);
+        }
+        completed_peers
+    }
+
+    /// Gives a newly generated chunk to the seeds.
+    pub(crate) fn generate_chunk(&mut self, chunk_number: usize) {
+        for peer in &mut self.peers[..self.number_seeds] {
+            peer.possessed_chunks[chunk_number] = true;
+            peer.announced_chunks[chunk_number] = true;
+        }
+        let chunk = &mut self.file.chunks[chunk_number];
+        chunk.number_possessing_peers += self.number_seeds;
+        chunk.number_ever_possessing_peers += self.number_seeds;
+        chunk.number_announcing_peers += self.number_seeds;
+        self.generated_chunks = chunk_number + 1;
+    }
+
+    /// Moves a chunk out of the live window. The active peers that do not
+    /// have the chunk miss it and cancel their downloads of it. Returns the
+    /// cancelled downloads and the peers that completed as a result.
+    pub(crate) fn expire_chunk<Obs: RunObserver>(
+        &mut self,
+        chunk_number: usize,
+        round_number: usize,
+        observer: &Obs,
+    ) -> (Vec<Download>, Vec<usize>) {
+        self.expired_chunks = chunk_number + 1;
+        let mut cancelled_downloads = vec![];
+        let mut completed_peers = vec![];
+        for peer_index in self.number_seeds..self.peers.len() {
+            let peer = &mut self.peers[peer_index];
+            if !peer.is_active {
+                continue;
+            }
+            if !peer.possessed_chunks[chunk_number] {
+                peer.missed_chunks += 1;
+                peer.received_blocks.remove(&chunk_number);
+                cancelled_downloads.extend(self.cancel_downloads(peer_index, chunk_number, None));
+            }
+            if self.check_peer_completed(peer_index, round_number, observer) {
+                completed_peers.push(peer_index);
+            }
+        }
+        (cancelled_downloads, completed_peers)
+    }
+}
@@ -1,9 +1,19 @@
+use chrono::Utc;
 use clap::Parser;
 use coppa::Distribution;
-use coppa::Strategy;
-use coppa::{Config, PeerConfig};
+use coppa::{
+    ArrivalSchedule, ChokingConfig, Coding, Config, DepartureConfig, Engine, LiveConfig,
+    PartialProgress, PeerConfig, Playback, PlaybackConfig, Round, SpeedDistribution, SpeedTiers,
+    Topology, TrackerConfig,
+};
 use coppa::{DebugRunObserver, EmptyRunObserver, SummaryRunObserver};
+use coppa::{
+    LocalRarestFirstSelector, RandomFirstSelector, SourceStrategy, Strategy, StreamingSelector,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use std::fs;
+use std::rc::Rc;
 use std::time::Duration;
 
 #[derive(Parser, Debug)]
@@ -11,6 +21,30 @@ struct Cli {
     /// Number of chunks in the distributed file
     #[arg(short, long)]
     chunks: usize,
+    /// Length of the file in bytes, which makes the last chunk shorter if needed
+    #[arg(long)]
+    file_length: Option<usize>,
+    /// Length of a chunk in bytes, with a file length
+    #[arg(long, default_value_t = 262144)]
+    chunk_length: usize,
+    /// File containing the size of each chunk relative to the default, one chunk per line
+    #[arg(long)]
+    chunk_sizes_file: Option<String>,
+    /// Number of blocks in a chunk, which can be downloaded from different sources
+    #[arg(long)]
+    blocks: Option<usize>,
+    /// Number of erasure-coded chunks, any number of chunks of which are enough to decode the file
+    #[arg(long)]
+    coded_chunks: Option<usize>,
+    /// Exchange random linear combinations of chunks with network coding
+    #[arg(long)]
+    network_coding: bool,
+    /// Number of files that split the chunks, each distributed in its own swarm
+    #[arg(long)]
+    files: Option<usize>,
+    /// Probability that a peer takes part in each swarm with several files
+    #[arg(long, default_value_t = 0.5)]
+    swarm_probability: f64,
     /// Total number of participating peers (including seeds)
     #[arg(short, long)]
     peers: usize,
@@ -23,9 +57,33 @@ struct Cli {
     /// Number of peers that do not distribute
     #[arg(long, default_value_t = 0)]
     freerider: usize,
+    /// Number of peers that upload only bad data, which fails the hash check
+    #[arg(long, default_value_t = 0)]
+    malicious: usize,
+    /// Probability that a downloaded chunk fails the hash check
+    #[arg(long)]
+    corruption_probability: Option<f64>,
+    /// Number of failed hash checks after which a peer bans a source
+    #[arg(long)]
+    ban_threshold: Option<usize>,
+    /// What a peer does with a partly downloaded block when the source leaves or stops uploading
+    #[arg(long, value_enum, default_value_t = PartialProgress::Drop)]
+    partial_progress: PartialProgress,
     /// Chunk selection strategy that all peers use
     #[arg(long, value_enum, default_value_t = Strategy::RarestFirst)]
     strategy: Strategy,
+    /// Number of peers whose chunks a peer counts with local rarest first
+    #[arg(long)]
+    rarity_sample_size: Option<usize>,
+    /// Number of chunks a peer picks randomly before rarest first with random first
+    #[arg(long)]
+    initial_random_chunks: Option<usize>,
+    /// Number of rounds ahead within which chunk deadlines are urgent with streaming
+    #[arg(long)]
+    streaming_lookahead: Option<f64>,
+    /// Source peer selection strategy that all peers use
+    #[arg(long, value_enum, default_value_t = SourceStrategy::NonSeedsFirst)]
+    source_strategy: SourceStrategy,
     /// The fast network speed
     #[arg(long)]
     speed_fast: Option<usize>,
@@ -35,9 +93,85 @@ struct Cli {
     /// The slow network speed
     #[arg(long)]
     speed_slow: Option<usize>,
+    /// The fast upload speed, if different from the fast network speed
+    #[arg(long)]
+    upload_speed_fast: Option<usize>,
+    /// The medium upload speed, if different from the medium network speed
+    #[arg(long)]
+    upload_speed_medium: Option<usize>,
+    /// The slow upload speed, if different from the slow network speed
+    #[arg(long)]
+    upload_speed_slow: Option<usize>,
+    /// Distribution of peer speeds, as uniform:MIN:MAX, lognormal:MU:SIGMA, or empirical:SPEED,...
+    #[arg(long)]
+    speed_distribution: Option<SpeedDistribution>,
+    /// Distribution of peer upload speeds, if different from the peer speeds
+    #[arg(long)]
+    upload_speed_distribution: Option<SpeedDistribution>,
+    /// File containing peer speeds, one peer per line as DOWNLOAD or DOWNLOAD UPLOAD
+    #[arg(long)]
+    peer_speeds_file: Option<String>,
+    /// Number of upload slots per peer, enables tit-for-tat choking
+    #[arg(long)]
+    upload_slots: Option<usize>,
+    /// Number of rounds between choking re-evaluations
+    #[arg(long, default_value_t = 1)]
+    rechoke_interval: usize,
+    /// Number of rounds between optimistic unchoke rotations
+    #[arg(long, default_value_t = 3)]
+    optimistic_unchoke_interval: usize,
+    /// Average number of peer arrivals per round, peers arrive as a flash crowd if not given
+    #[arg(long)]
+    arrival_rate: Option<f64>,
+    /// Number of rounds that peers seed after completion before leaving
+    #[arg(long)]
+    seeding_rounds: Option<usize>,
+    /// Probability per round that a downloading peer aborts and leaves
+    #[arg(long, default_value_t = 0.0)]
+    abort_probability: f64,
+    /// Overlay network of peers, as complete, regular:DEGREE, random:PROBABILITY,
+    /// small-world:DEGREE:PROBABILITY, or scale-free:EDGES
+    #[arg(long)]
+    topology: Option<Topology>,
+    /// File containing the overlay network, one connection per line as PEER PEER
+    #[arg(long)]
+    topology_file: Option<String>,
+    /// Maximum number of peers in a tracker response, enables the tracker
+    #[arg(long)]
+    tracker_peers: Option<usize>,
+    /// Number of rounds between announces of a peer to the tracker
+    #[arg(long, default_value_t = 10)]
+    announce_interval: usize,
     /// File containing peer configuration, one peer per line
     #[arg(short = 'F', long)]
     peer_config_file: Option<String>,
+    /// Number of rounds before other peers learn that a peer has downloaded a chunk
+    #[arg(long, default_value_t = 0)]
+    announcement_delay: usize,
+    /// Make the seeds offer each chunk to only one peer at a time
+    #[arg(long)]
+    super_seeding: bool,
+    /// Number of missing chunks at which a peer enters endgame mode
+    #[arg(long)]
+    endgame_chunks: Option<usize>,
+    /// Number of rounds between chunks generated by the seeds, enables live streaming
+    #[arg(long)]
+    live_interval: Option<usize>,
+    /// Number of latest chunks that peers download with live streaming
+    #[arg(long, default_value_t = 10)]
+    live_window: usize,
+    /// Number of chunks per round that peers play while downloading, enables playback
+    #[arg(long)]
+    playback_rate: Option<f64>,
+    /// Number of rounds after joining before a peer starts playback
+    #[arg(long, default_value_t = 0)]
+    startup_delay: usize,
+    /// Simulate with continuous time instead of rounds
+    #[arg(long)]
+    event_driven: bool,
+    /// Latency of a chunk transfer in rounds, with continuous time
+    #[arg(long, default_value_t = 0.0)]
+    latency: f64,
     /// Seed to use for random number generation
     #[arg(long)]
     random_seed: Option<u64>,
@@ -51,6 +185,17 @@ struct Cli {
 
 impl Cli {
     pub fn assert_consistency(&self) {
+        assert!(self.file_length.is_none() || self.chunk_sizes_file.is_none());
+        assert!(self.seeds + self.malicious <= self.peers);
+        assert!(self.coded_chunks.is_none() || !self.network_coding);
+        assert!(self.topology.is_none() || self.topology_file.is_none());
+        if self.tracker_peers.is_some() {
+            assert!(self.topology.is_none());
+            assert!(self.topology_file.is_none());
+        }
+        assert!(self.rarity_sample_size.is_none() || self.strategy == Strategy::LocalRarestFirst);
+        assert!(self.initial_random_chunks.is_none() || self.strategy == Strategy::RandomFirst);
+        assert!(self.streaming_lookahead.is_none() || self.strategy == Strategy::Streaming);
         if self.peer_config_file.is_some() {
             assert!(self.selfish == 0);
             assert!(self.freerider == 0);
@@ -65,50 +210,398 @@ fn main() {
     let speed_slow = cli.speed_slow.unwrap_or(1);
     let speed_medium = cli.speed_medium.unwrap_or(speed_slow);
     let speed_fast = cli.speed_fast.unwrap_or(speed_medium);
-    let config = if let Some(peer_config_file) = cli.peer_config_file {
+    let speed_tiers = SpeedTiers::new(speed_fast, speed_medium, speed_slow).with_upload_speeds(
+        cli.upload_speed_fast.unwrap_or(speed_fast),
+        cli.upload_speed_medium.unwrap_or(speed_medium),
+        cli.upload_speed_slow.unwrap_or(speed_slow),
+    );
+    let mut config = if let Some(peer_config_file) = cli.peer_config_file {
         let mut peer_config_contents = fs::read(peer_config_file.clone())
             .unwrap_or_else(|_| panic!("Could not read file {peer_config_file}"));
         peer_config_contents.truncate(peer_config_contents.len() - 1);
         let peer_config_strings = peer_config_contents.split(|c| *c == b'\n');
         let peer_config = peer_config_strings.map(PeerConfig::from_string).collect();
-        Config::from_peer_config(
-            cli.chunks,
-            cli.peers,
-            cli.seeds,
-            speed_fast,
-            speed_medium,
-            speed_slow,
-            peer_config,
-        )
+        Config::from_peer_config(cli.chunks, cli.peers, cli.seeds, speed_tiers, peer_config)
     } else {
         Config::from_counts(
             cli.chunks,
             cli.peers,
             cli.seeds,
-            speed_fast,
-            speed_medium,
-            speed_slow,
+            speed_tiers,
             cli.selfish,
             cli.freerider,
             cli.strategy,
         )
     };
+    for peer in 0..cli.peers {
+        config.set_source_selector(peer, cli.source_strategy.source_selector());
+        if let Some(sample_size) = cli.rarity_sample_size {
+            config.set_chunk_selector(peer, Rc::new(LocalRarestFirstSelector::new(sample_size)));
+        }
+        if let Some(initial_random_chunks) = cli.initial_random_chunks {
+            config.set_chunk_selector(
+                peer,
+                Rc::new(RandomFirstSelector::new(initial_random_chunks)),
+            );
+        }
+        if let Some(lookahead) = cli.streaming_lookahead {
+            config.set_chunk_selector(peer, Rc::new(StreamingSelector::new(lookahead)));
+        }
+    }
+    if let Some(file_length) = cli.file_length {
+        config.set_file_length(file_length, cli.chunk_length);
+    }
+    if let Some(chunk_sizes_file) = &cli.chunk_sizes_file {
+        let chunk_sizes_contents = fs::read_to_string(chunk_sizes_file)
+            .unwrap_or_else(|_| panic!("Could not read file {chunk_sizes_file}"));
+        let chunk_sizes = chunk_sizes_contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.trim()
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid chunk size {line} in {chunk_sizes_file}"))
+            })
+            .collect();
+        config.set_chunk_sizes(chunk_sizes);
+    }
+    if let Some(coded_chunks) = cli.coded_chunks {
+        config.set_coding(Coding::Erasure { coded_chunks });
+    }
+    if cli.network_coding {
+        config.set_coding(Coding::Network);
+    }
+    if let Some(blocks_per_chunk) = cli.blocks {
+        config.set_blocks_per_chunk(blocks_per_chunk);
+    }
+    for peer in cli.seeds..cli.seeds + cli.malicious {
+        config.set_malicious(peer, true);
+    }
+    if let Some(corruption_probability) = cli.corruption_probability {
+        config.set_corruption_probability(corruption_probability);
+    }
+    config.set_partial_progress(cli.partial_progress);
+    if let Some(ban_threshold) = cli.ban_threshold {
+        config.set_ban_threshold(ban_threshold);
+    }
+    if let Some(upload_slots) = cli.upload_slots {
+        config.set_choking(ChokingConfig::new(
+            upload_slots,
+            cli.rechoke_interval,
+            cli.optimistic_unchoke_interval,
+        ));
+    }
+    if let Some(rate) = cli.arrival_rate {
+        config.set_arrivals(ArrivalSchedule::Poisson { rate });
+    }
+    config.set_departures(DepartureConfig::new(
+        cli.seeding_rounds,
+        cli.abort_probability,
+    ));
+    if let Some(topology) = &cli.topology {
+        config.set_topology(topology.clone());
+    }
+    if let Some(topology_file) = &cli.topology_file {
+        let topology_contents = fs::read_to_string(topology_file)
+            .unwrap_or_else(|_| panic!("Could not read file {topology_file}"));
+        let edges = topology_contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let peers: Vec<usize> = line
+                    .split_whitespace()
+                    .map(|p| {
+                        p.parse()
+                            .unwrap_or_else(|_| panic!("Invalid peer {p} in {topology_file}"))
+                    })
+                    .collect();
+                assert!(
+                    peers.len() == 2,
+                    "Invalid connection {line} in {topology_file}"
+                );
+                (peers[0], peers[1])
+            })
+            .collect();
+        config.set_topology(Topology::Edges(edges));
+    }
+    if let Some(peer_list_size) = cli.tracker_peers {
+        config.set_tracker(TrackerConfig::new(peer_list_size, cli.announce_interval));
+    }
+    config.set_announcement_delay(cli.announcement_delay);
+    config.set_super_seeding(cli.super_seeding);
+    if let Some(endgame_chunks) = cli.endgame_chunks {
+        config.set_endgame(endgame_chunks);
+    }
+    if let Some(chunk_interval) = cli.live_interval {
+        config.set_live(LiveConfig::new(chunk_interval, cli.live_window));
+    }
+    if let Some(playback_rate) = cli.playback_rate {
+        config.set_playback(PlaybackConfig::new(playback_rate, cli.startup_delay));
+    }
+    if cli.event_driven {
+        config.set_engine(Engine::Events {
+            latency: cli.latency,
+        });
+    }
+    let random_seed = cli.random_seed.unwrap_or(Utc::now().timestamp() as u64);
+    let mut rng = ChaCha8Rng::seed_from_u64(random_seed);
+    if let Some(number_files) = cli.files {
+        config.sample_swarms(number_files, cli.swarm_probability, &mut rng);
+    }
+    if let Some(speed_distribution) = &cli.speed_distribution {
+        config.sample_speeds(speed_distribution, &mut rng);
+    }
+    if let Some(upload_speed_distribution) = &cli.upload_speed_distribution {
+        config.sample_upload_speeds(upload_speed_distribution, &mut rng);
+    }
+    if let Some(peer_speeds_file) = cli.peer_speeds_file {
+        let peer_speeds_contents = fs::read_to_string(peer_speeds_file.clone())
+            .unwrap_or_else(|_| panic!("Could not read file {peer_speeds_file}"));
+        let lines: Vec<&str> = peer_speeds_contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        if lines.len() > cli.peers {
+            panic!(
+                "{peer_speeds_file} gives the speeds of {:?} peers, but there are only {:?} peers",
+                lines.len(),
+                cli.peers
+            );
+        }
+        for (peer, line) in lines.into_iter().enumerate() {
+            let speeds: Vec<f64> = line
+                .split_whitespace()
+                .map(|s| {
+                    s.parse()
+                        .unwrap_or_else(|_| panic!("Invalid speed {s} in {peer_speeds_file}"))
+                })
+                .collect();
+            let download_speed = speeds[0];
+            let upload_speed = *speeds.get(1).unwrap_or(&download_speed);
+            config.set_speeds(peer, upload_speed, download_speed);
+        }
+    }
     let mut distribution = Distribution::new(&config);
     let rounds = if cli.silent {
-        distribution.run(cli.random_seed, EmptyRunObserver)
+        distribution.run(Some(random_seed), EmptyRunObserver)
     } else if cli.verbose {
-        distribution.run(cli.random_seed, DebugRunObserver)
+        distribution.run(Some(random_seed), DebugRunObserver)
     } else {
-        distribution.run(cli.random_seed, SummaryRunObserver)
+        distribution.run(Some(random_seed), SummaryRunObserver)
     };
     let mut exchanged_chunks = 0;
+    let mut wasted_chunks = 0.0;
+    let mut corrupted_chunks = 0;
+    let mut bans = 0;
+    let mut aborted_downloads = 0;
     let mut execution_time = Duration::from_secs(0);
     for round in &rounds {
         exchanged_chunks += round.exchanged_chunks;
+        wasted_chunks += round.wasted_chunks;
+        corrupted_chunks += round.corrupted_chunks;
+        bans += round.bans;
+        aborted_downloads += round.aborted_downloads;
         execution_time += round.execution_time;
     }
     println!();
     println!("Number of rounds {:?}", rounds.len() - 1);
     println!("Number of chunks exchanged {exchanged_chunks:?}");
+    if cli.super_seeding {
+        let seed_uploads: usize = distribution.peers[..distribution.number_seeds]
+            .iter()
+            .map(|p| p.number_uploads)
+            .sum();
+        let upload_unit = if cli.blocks.is_some() {
+            "blocks"
+        } else {
+            "chunks"
+        };
+        println!("Number of {upload_unit} uploaded by seeds {seed_uploads:?}");
+        let first_copy_round: Option<usize> = distribution
+            .file
+            .chunks
+            .iter()
+            .map(|c| c.first_copy_round)
+            .try_fold(0, |latest, round| round.map(|r| latest.max(r)));
+        if let Some(first_copy_round) = first_copy_round {
+            let seed_uploads: usize = rounds[..=first_copy_round]
+                .iter()
+                .map(|r| r.seed_uploads)
+                .sum();
+            println!(
+                "Number of {upload_unit} uploaded by seeds until the peers had every chunk {seed_uploads:?}"
+            );
+        }
+    }
+    if cli.endgame_chunks.is_some() {
+        println!("Number of chunks wasted in endgame {wasted_chunks:?}");
+    } else if cli.coded_chunks.is_some() {
+        println!("Number of chunks wasted after decoding {wasted_chunks:?}");
+    }
+    if cli.malicious > 0 || cli.corruption_probability.is_some() {
+        println!("Number of chunks that failed the hash check {corrupted_chunks:?}");
+        println!("Number of bans {bans:?}");
+    }
+    if aborted_downloads > 0 {
+        println!("Number of aborted downloads {aborted_downloads:?}");
+    }
+    if cli.playback_rate.is_some() {
+        print_playback_summary(&distribution, cli.verbose);
+    }
+    if cli.live_interval.is_some() {
+        print_live_summary(&distribution, cli.verbose);
+    }
+    if cli.files.is_some() {
+        print_swarm_summary(&distribution, &rounds);
+    }
+    if config.has_adversaries() {
+        let mut honest_config = config.clone();
+        honest_config.remove_adversaries();
+        let mut honest_distribution = Distribution::new(&honest_config);
+        honest_distribution.run(Some(random_seed), EmptyRunObserver);
+        print_adversary_summary(&distribution, &honest_distribution);
+    }
+    if cli.coded_chunks.is_some() || cli.network_coding {
+        let mut plain_config = config.clone();
+        plain_config.remove_coding();
+        let mut plain_distribution = Distribution::new(&plain_config);
+        plain_distribution.run(Some(random_seed), EmptyRunObserver);
+        print_coding_summary(&distribution, &plain_distribution);
+    }
     println!("Execution time {execution_time:?}");
 }
+
+fn print_playback_summary(distribution: &Distribution, verbose: bool) {
+    let playbacks: Vec<(usize, &Playback)> = distribution
+        .peers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.playback.as_ref().map(|playback| (i, playback)))
+        .collect();
+    if verbose {
+        for (peer, playback) in &playbacks {
+            println!(
+                "Peer {peer:?}: startup latency {:?} stalls {:?} stall duration {:?}",
+                playback.startup_latency, playback.stall_count, playback.stall_duration
+            );
+        }
+    }
+    let started: Vec<f64> = playbacks
+        .iter()
+        .filter_map(|(_, p)| p.startup_latency)
+        .collect();
+    let number_peers = playbacks.len() as f64;
+    if started.is_empty() {
+        println!("Average startup latency n/a");
+    } else {
+        println!(
+            "Average startup latency {:?}",
+            started.iter().sum::<f64>() / started.len() as f64
+        );
+    }
+    println!(
+        "Average number of stalls {:?}",
+        playbacks.iter().map(|(_, p)| p.stall_count).sum::<usize>() as f64 / number_peers
+    );
+    println!(
+        "Average stall duration {:?}",
+        playbacks.iter().map(|(_, p)| p.stall_duration).sum::<f64>() / number_peers
+    );
+}
+
+fn print_live_summary(distribution: &Distribution, verbose: bool) {
+    let peers = &distribution.peers[distribution.number_seeds..];
+    if verbose {
+        for (i, peer) in peers.iter().enumerate() {
+            println!(
+                "Peer {:?}: missed chunks {:?} continuity {:?}",
+                distribution.number_seeds + i,
+                peer.missed_chunks,
+                peer.continuity()
+            );
+        }
+    }
+    let continuities: Vec<f64> = peers.iter().filter_map(|p| p.continuity()).collect();
+    if continuities.is_empty() {
+        println!("Average continuity n/a");
+    } else {
+        println!(
+            "Average continuity {:?}",
+            continuities.iter().sum::<f64>() / continuities.len() as f64
+        );
+    }
+}
+
+/// Compares the completion rounds of the honest peers to those of the same
+/// peers in a distribution where every peer is honest.
+fn print_adversary_summary(distribution: &Distribution, honest_distribution: &Distribution) {
+    let honest_peers: Vec<usize> = distribution
+        .peers
+        .iter()
+        .enumerate()
+        .skip(distribution.number_seeds)
+        .filter(|(_, p)| p.is_honest())
+        .map(|(i, _)| i)
+        .collect();
+    let (Some(with_adversaries), Some(without_adversaries)) = (
+        average_completion_round(distribution, &honest_peers),
+        average_completion_round(honest_distribution, &honest_peers),
+    ) else {
+        println!("Slowdown of honest peers n/a");
+        return;
+    };
+    println!(
+        "Average completion round of honest peers {with_adversaries:?} without adversaries {without_adversaries:?}"
+    );
+    println!(
+        "Slowdown of honest peers {:?}",
+        with_adversaries / without_adversaries
+    );
+}
+
+/// Compares the completion rounds of the peers to those of the same peers
+/// in a distribution where they exchange the chunks of the file uncoded.
+fn print_coding_summary(distribution: &Distribution, plain_distribution: &Distribution) {
+    let peers: Vec<usize> = (distribution.number_seeds..distribution.peers.len()).collect();
+    let (Some(with_coding), Some(without_coding)) = (
+        average_completion_round(distribution, &peers),
+        average_completion_round(plain_distribution, &peers),
+    ) else {
+        println!("Speedup from coding n/a");
+        return;
+    };
+    println!("Average completion round of peers {with_coding:?} without coding {without_coding:?}");
+    println!("Speedup from coding {:?}", without_coding / with_coding);
+}
+
+/// The average completion round of the given peers that completed, if any
+/// did.
+fn average_completion_round(distribution: &Distribution, peers: &[usize]) -> Option<f64> {
+    let completion_rounds: Vec<usize> = peers
+        .iter()
+        .filter_map(|p| distribution.peers[*p].completion_round)
+        .collect();
+    if completion_rounds.is_empty() {
+        return None;
+    }
+    Some(completion_rounds.iter().sum::<usize>() as f64 / completion_rounds.len() as f64)
+}
+
+fn print_swarm_summary(distribution: &Distribution, rounds: &[Round]) {
+    for (i, swarm) in distribution.swarms.iter().enumerate() {
+        let exchanged_chunks: usize = rounds.iter().map(|r| r.swarms[i].exchanged_chunks).sum();
+        let completion_rounds: Vec<usize> = swarm.peers[distribution.number_seeds..]
+            .iter()
+            .filter_map(|p| distribution.peers[*p].swarm_completion_rounds[i])
+            .collect();
+        println!(
+            "Swarm {i:?}: chunks {:?} peers {:?} exchanged {exchanged_chunks:?} average completion round {:?} last completion round {:?}",
+            swarm.chunks.len(),
+            swarm.peers.len(),
+            (!completion_rounds.is_empty()).then(|| {
+                completion_rounds.iter().sum::<usize>() as f64 / completion_rounds.len() as f64
+            }),
+            completion_rounds.iter().max()
+        );
+    }
+}
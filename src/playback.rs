@@ -0,0 +1,90 @@
+use crate::{Distribution, Peer, Playback, PlaybackConfig};
+
+impl Playback {
+    pub(crate) fn new(config: PlaybackConfig) -> Playback {
+        Playback {
+            config,
+            start_time: None,
+            startup_latency: None,
+            position: 0.0,
+            stall_count: 0,
+            stall_duration: 0.0,
+            is_stalled: false,
+        }
+    }
+
+    /// The time by which a chunk needs to be downloaded for playback to
+    /// reach it without stalling. Before playback has started, the earliest
+    /// possible start time is assumed.
+    pub fn deadline(&self, chunk_number: usize, join_time: f64) -> f64 {
+        let start_time = self
+            .start_time
+            .unwrap_or(join_time + self.config.startup_delay as f64);
+        start_time + self.stall_duration + chunk_number as f64 / self.config.playback_rate
+    }
+
+    /// Plays the file for the duration of a round, starting playback first
+    /// if the startup delay has passed and the first chunk is available.
+    fn advance(&mut self, possessed_chunks: &[bool], join_time: f64, round_number: usize) {
+        let round_start_time = (round_number - 1) as f64;
+        if self.start_time.is_none() {
+            if round_start_time < join_time + self.config.startup_delay as f64
+                || !possessed_chunks[0]
+            {
+                return;
+            }
+            self.start_time = Some(round_start_time);
+            self.startup_latency = Some(round_start_time - join_time);
+        }
+        let mut remaining_time = 1.0;
+        while remaining_time > 0.0 {
+            let chunk_number = self.position as usize;
+            if chunk_number >= possessed_chunks.len() {
+                break;
+            }
+            if !possessed_chunks[chunk_number] {
+                if !self.is_stalled {
+                    self.stall_count += 1;
+                    self.is_stalled = true;
+                }
+                self.stall_duration += remaining_time;
+                break;
+            }
+            self.is_stalled = false;
+            let chunk_end = (chunk_number + 1) as f64;
+            let chunk_time = (chunk_end - self.position) / self.config.playback_rate;
+            if chunk_time <= remaining_time {
+                self.position = chunk_end;
+                remaining_time -= chunk_time;
+            } else {
+                self.position += remaining_time * self.config.playback_rate;
+                remaining_time = 0.0;
+            }
+        }
+    }
+}
+
+impl Peer {
+    /// The playback deadline of a chunk, or `None` if the peer does not
+    /// play the file.
+    pub fn chunk_deadline(&self, chunk_number: usize) -> Option<f64> {
+        self.playback
+            .as_ref()
+            .map(|p| p.deadline(chunk_number, self.join_time))
+    }
+}
+
+impl Distribution {
+    /// Advances the playback of the active peers over a round, using the
+    /// chunks they possess at the start of the round.
+    pub(crate) fn advance_playback(&mut self, round_number: usize) {
+        for peer in &mut self.peers {
+            if !peer.is_active {
+                continue;
+            }
+            if let Some(playback) = &mut peer.playback {
+                playback.advance(&peer.possessed_chunks, peer.join_time, round_number);
+            }
+        }
+    }
+}
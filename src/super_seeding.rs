@@ -0,0 +1,73 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::Distribution;
+
+impl Distribution {
+    /// Updates the chunk that each super-seeding seed offers to a peer
+    /// before the peer selects chunks to download.
+    ///
+    /// A seed keeps its offer while the peer is still missing the offered
+    /// chunk, and after the peer has downloaded it until another peer that
+    /// is not a seed has the chunk as well. A new offer is the rarest chunk
+    /// that the peer is missing and that the seed has not offered to any
+    /// other active peer that is still missing it or has not uploaded it
+    /// further. If no such chunk remains, for example because a peer never
+    /// uploads its offered chunk, chunks that are still spreading may be
+    /// offered again.
+    pub(crate) fn update_super_seeding_offers<R: Rng + ?Sized>(
+        &mut self,
+        peer_index: usize,
+        rng: &mut R,
+    ) {
+        for seed_index in 0..self.number_seeds {
+            let seed = &self.peers[seed_index];
+            let Some(offers) = &seed.super_seeding_offers else {
+                continue;
+            };
+            let peer = &self.peers[peer_index];
+            if let Some(offered_chunk) = offers.get(&peer_index) {
+                let is_uploaded_further = self.file.chunks[*offered_chunk].number_announcing_peers
+                    > self.number_seeds + 1;
+                if !peer.possessed_chunks[*offered_chunk] || !is_uploaded_further {
+                    continue;
+                }
+            }
+            let is_candidate = |c: usize, include_spreading: bool| {
+                seed.announced_chunks[c]
+                    && !peer.possessed_chunks[c]
+                    && self.wants_chunk(peer_index, c)
+                    && !offers.iter().any(|(p, offered_chunk)| {
+                        let other = &self.peers[*p];
+                        *p != peer_index
+                            && *offered_chunk == c
+                            && other.is_active
+                            && (!other.possessed_chunks[c]
+                                || !include_spreading
+                                    && self.file.chunks[c].number_announcing_peers
+                                        <= self.number_seeds + 1)
+                    })
+            };
+            let mut candidates: Vec<usize> = (0..self.file.chunks.len())
+                .filter(|c| is_candidate(*c, false))
+                .collect();
+            if candidates.is_empty() {
+                candidates = (0..self.file.chunks.len())
+                    .filter(|c| is_candidate(*c, true))
+                    .collect();
+            }
+            candidates.shuffle(rng);
+            let new_offer = candidates
+                .into_iter()
+                .min_by_key(|c| self.file.chunks[*c].number_announcing_peers);
+            let offers = self.peers[seed_index]
+                .super_seeding_offers
+                .as_mut()
+                .unwrap();
+            match new_offer {
+                Some(chunk_number) => offers.insert(peer_index, chunk_number),
+                None => offers.remove(&peer_index),
+            };
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{Distribution, RunObserver, Swarm, SwarmConfig, SwarmRound};
+
+impl Distribution {
+    /// Lays out the files of the swarms one after another in the combined
+    /// file, and adds the seeds to every swarm.
+    pub(crate) fn build_swarms(swarms: &[SwarmConfig], number_seeds: usize) -> Vec<Swarm> {
+        let mut first_chunk = 0;
+        swarms
+            .iter()
+            .map(|config| {
+                let mut peers: Vec<usize> = (0..number_seeds).collect();
+                peers.extend(&config.peers);
+                peers.sort_unstable();
+                peers.dedup();
+                let chunks = first_chunk..first_chunk + config.number_chunks;
+                first_chunk = chunks.end;
+                Swarm { chunks, peers }
+            })
+            .collect()
+    }
+
+    /// Returns the swarm of the file that a chunk belongs to, if several
+    /// files are distributed.
+    fn swarm_of(&self, chunk_number: usize) -> Option<usize> {
+        self.swarms
+            .iter()
+            .position(|s| s.chunks.contains(&chunk_number))
+    }
+
+    /// Returns whether a peer wants to download a chunk, which is when it
+    /// takes part in the swarm of the chunk.
+    pub(crate) fn wants_chunk(&self, peer_index: usize, chunk_number: usize) -> bool {
+        self.swarm_of(chunk_number)
+            .is_none_or(|s| self.swarms[s].peers.binary_search(&peer_index).is_ok())
+    }
+
+    /// The number of peers that take part in the swarm of a chunk.
+    pub(crate) fn number_wanting_peers(&self, chunk_number: usize) -> usize {
+        match self.swarm_of(chunk_number) {
+            Some(swarm) => self.swarms[swarm].peers.len(),
+            None => self.peers.len(),
+        }
+    }
+
+    pub(crate) fn record_swarm_exchange(&mut self, chunk_number: usize) {
+        if let Some(swarm) = self.swarm_of(chunk_number) {
+            self.swarm_rounds[swarm].exchanged_chunks += 1;
+        }
+    }
+
+    pub(crate) fn record_swarm_chunk_completed(&mut self, chunk_number: usize) {
+        if let Some(swarm) = self.swarm_of(chunk_number) {
+            self.swarm_rounds[swarm].completed_chunks += 1;
+        }
+    }
+
+    /// Records the completion of the files of the swarms in which a peer
+    /// has now downloaded every chunk.
+    pub(crate) fn check_swarms_completed<Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        round_number: usize,
+        observer: &Obs,
+    ) {
+        for (swarm_index, swarm) in self.swarms.iter().enumerate() {
+            let peer = &mut self.peers[peer_index];
+            if peer.swarm_completion_rounds[swarm_index].is_none()
+                && swarm.peers.binary_search(&peer_index).is_ok()
+                && peer.possessed_chunks[swarm.chunks.clone()]
+                    .iter()
+                    .all(|c| *c)
+            {
+                observer.swarm_completed(peer_index, swarm_index);
+                peer.swarm_completion_rounds[swarm_index] = Some(round_number);
+                self.swarm_rounds[swarm_index].completed_peers += 1;
+            }
+        }
+    }
+
+    /// Returns the statistics of each swarm for the round that is ending,
+    /// and starts counting exchanged chunks from zero for the next round.
+    pub(crate) fn take_swarm_rounds(&mut self) -> Vec<SwarmRound> {
+        let swarm_rounds = self.swarm_rounds.clone();
+        for swarm_round in &mut self.swarm_rounds {
+            swarm_round.exchanged_chunks = 0;
+        }
+        swarm_rounds
+    }
+}
@@ -0,0 +1,228 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::str::FromStr;
+
+use crate::{Distribution, Topology};
+
+/// The number of random attempts to find a pair of peers to connect in a
+/// random regular graph before looking through all the possible pairs.
+const MAX_RANDOM_PAIRING_ATTEMPTS: usize = 100;
+
+impl Topology {
+    /// Builds the overlay graph for `number_peers` peers, returning the
+    /// sorted neighbours of each peer, or `None` if every peer is a
+    /// neighbour of every other peer.
+    fn neighbours<R: Rng + ?Sized>(
+        &self,
+        number_peers: usize,
+        rng: &mut R,
+    ) -> Option<Vec<Vec<usize>>> {
+        let mut neighbours = match self {
+            Topology::Complete => return None,
+            Topology::RandomRegular { degree } => random_regular(number_peers, *degree, rng),
+            Topology::ErdosRenyi { probability } => {
+                let mut neighbours = vec![vec![]; number_peers];
+                for a in 0..number_peers {
+                    for b in a + 1..number_peers {
+                        if rng.gen_bool(*probability) {
+                            connect(&mut neighbours, a, b);
+                        }
+                    }
+                }
+                neighbours
+            }
+            Topology::SmallWorld {
+                degree,
+                rewiring_probability,
+            } => small_world(number_peers, *degree, *rewiring_probability, rng),
+            Topology::ScaleFree { edges_per_peer } => {
+                scale_free(number_peers, *edges_per_peer, rng)
+            }
+            Topology::Edges(edges) => {
+                let mut neighbours = vec![vec![]; number_peers];
+                for (a, b) in edges {
+                    connect(&mut neighbours, *a, *b);
+                }
+                neighbours
+            }
+        };
+        if !matches!(self, Topology::Edges(_)) {
+            // Generated graphs give a special position to some peers, such
+            // as the first peers in a scale-free graph, which should not
+            // depend on which peers are seeds.
+            let mut permutation: Vec<usize> = (0..number_peers).collect();
+            permutation.shuffle(rng);
+            let mut permuted = vec![vec![]; number_peers];
+            for (peer, peer_neighbours) in neighbours.into_iter().enumerate() {
+                permuted[permutation[peer]] =
+                    peer_neighbours.iter().map(|n| permutation[*n]).collect();
+            }
+            neighbours = permuted;
+        }
+        for peer_neighbours in &mut neighbours {
+            peer_neighbours.sort();
+        }
+        Some(neighbours)
+    }
+}
+
+fn connect(neighbours: &mut [Vec<usize>], a: usize, b: usize) {
+    if !neighbours[a].contains(&b) {
+        neighbours[a].push(b);
+        neighbours[b].push(a);
+    }
+}
+
+fn disconnect(neighbours: &mut [Vec<usize>], a: usize, b: usize) {
+    neighbours[a].retain(|n| *n != b);
+    neighbours[b].retain(|n| *n != a);
+}
+
+/// Generates a random regular graph by repeatedly connecting two random
+/// peers that still need neighbours, starting over if no such pair of
+/// unconnected peers is left.
+fn random_regular<R: Rng + ?Sized>(
+    number_peers: usize,
+    degree: usize,
+    rng: &mut R,
+) -> Vec<Vec<usize>> {
+    'attempt: loop {
+        let mut neighbours = vec![vec![]; number_peers];
+        let mut stubs: Vec<usize> = (0..number_peers)
+            .flat_map(|p| std::iter::repeat_n(p, degree))
+            .collect();
+        while !stubs.is_empty() {
+            let is_suitable = |neighbours: &[Vec<usize>], i: usize, j: usize| {
+                stubs[i] != stubs[j] && !neighbours[stubs[i]].contains(&stubs[j])
+            };
+            let mut pair = (0..MAX_RANDOM_PAIRING_ATTEMPTS)
+                .map(|_| (rng.gen_range(0..stubs.len()), rng.gen_range(0..stubs.len())))
+                .find(|(i, j)| is_suitable(&neighbours, *i, *j));
+            if pair.is_none() {
+                let suitable_pairs: Vec<(usize, usize)> = (0..stubs.len())
+                    .flat_map(|i| (i + 1..stubs.len()).map(move |j| (i, j)))
+                    .filter(|(i, j)| is_suitable(&neighbours, *i, *j))
+                    .collect();
+                pair = suitable_pairs.choose(rng).copied();
+            }
+            let Some((i, j)) = pair else {
+                continue 'attempt;
+            };
+            connect(&mut neighbours, stubs[i], stubs[j]);
+            stubs.swap_remove(i.max(j));
+            stubs.swap_remove(i.min(j));
+        }
+        return neighbours;
+    }
+}
+
+fn small_world<R: Rng + ?Sized>(
+    number_peers: usize,
+    degree: usize,
+    rewiring_probability: f64,
+    rng: &mut R,
+) -> Vec<Vec<usize>> {
+    let mut neighbours = vec![vec![]; number_peers];
+    for distance in 1..=degree / 2 {
+        for peer in 0..number_peers {
+            connect(&mut neighbours, peer, (peer + distance) % number_peers);
+        }
+    }
+    for distance in 1..=degree / 2 {
+        for peer in 0..number_peers {
+            let neighbour = (peer + distance) % number_peers;
+            if !neighbours[peer].contains(&neighbour) || !rng.gen_bool(rewiring_probability) {
+                continue;
+            }
+            let candidates: Vec<usize> = (0..number_peers)
+                .filter(|p| *p != peer && !neighbours[peer].contains(p))
+                .collect();
+            if let Some(new_neighbour) = candidates.choose(rng) {
+                disconnect(&mut neighbours, peer, neighbour);
+                connect(&mut neighbours, peer, *new_neighbour);
+            }
+        }
+    }
+    neighbours
+}
+
+fn scale_free<R: Rng + ?Sized>(
+    number_peers: usize,
+    edges_per_peer: usize,
+    rng: &mut R,
+) -> Vec<Vec<usize>> {
+    let mut neighbours = vec![vec![]; number_peers];
+    // Every peer appears here once for each of its neighbours, so that a
+    // uniform choice from this is proportional to the number of neighbours.
+    let mut endpoints = vec![];
+    let initial_peers = edges_per_peer + 1;
+    for a in 0..initial_peers {
+        for b in a + 1..initial_peers {
+            connect(&mut neighbours, a, b);
+            endpoints.extend([a, b]);
+        }
+    }
+    for peer in initial_peers..number_peers {
+        let mut targets = Vec::with_capacity(edges_per_peer);
+        while targets.len() < edges_per_peer {
+            let target = *endpoints.choose(rng).unwrap();
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        for target in targets {
+            connect(&mut neighbours, peer, target);
+            endpoints.extend([peer, target]);
+        }
+    }
+    neighbours
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    /// Parses `complete`, `regular:DEGREE`, `random:PROBABILITY`,
+    /// `small-world:DEGREE:PROBABILITY`, or `scale-free:EDGES`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_degree = |p: &str| {
+            p.parse::<usize>()
+                .map_err(|_| format!("Invalid topology parameter {p}"))
+        };
+        let parse_probability = |p: &str| {
+            p.parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=1.0).contains(p))
+                .ok_or_else(|| format!("Invalid topology parameter {p}"))
+        };
+        let parts: Vec<&str> = s.split(':').collect();
+        let topology = match parts.as_slice() {
+            ["complete"] => Topology::Complete,
+            ["regular", degree] => Topology::RandomRegular {
+                degree: parse_degree(degree)?,
+            },
+            ["random", probability] => Topology::ErdosRenyi {
+                probability: parse_probability(probability)?,
+            },
+            ["small-world", degree, probability] => Topology::SmallWorld {
+                degree: parse_degree(degree)?,
+                rewiring_probability: parse_probability(probability)?,
+            },
+            ["scale-free", edges] => Topology::ScaleFree {
+                edges_per_peer: parse_degree(edges)?,
+            },
+            _ => return Err(format!("Invalid topology {s}")),
+        };
+        Ok(topology)
+    }
+}
+
+impl Distribution {
+    pub(crate) fn build_overlay<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let Some(neighbours) = self.topology.neighbours(self.peers.len(), rng) else {
+            return;
+        };
+        for (peer, peer_neighbours) in self.peers.iter_mut().zip(neighbours) {
+            peer.neighbours = Some(peer_neighbours);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::{Distribution, RunObserver};
+
+impl Distribution {
+    /// Starts every peer without any known peers, when peers learn about
+    /// each other only from the tracker.
+    pub(crate) fn clear_peer_lists(&mut self) {
+        if self.tracker.is_none() {
+            return;
+        }
+        for peer in &mut self.peers {
+            peer.neighbours = Some(vec![]);
+        }
+    }
+
+    /// Returns whether a peer should announce itself to the tracker in the
+    /// given round, which is when it has just joined or when the announce
+    /// interval has passed since its previous announce.
+    pub(crate) fn is_announce_due(&self, peer_index: usize, round_number: usize) -> bool {
+        let Some(tracker) = self.tracker else {
+            return false;
+        };
+        let peer = &self.peers[peer_index];
+        peer.is_active
+            && peer
+                .last_announce_round
+                .is_none_or(|r| round_number >= r + tracker.announce_interval)
+    }
+
+    /// Announces a peer to the tracker, which responds with at most
+    /// `peer_list_size` random peers among the other active peers. The
+    /// peer connects to the ones it did not know yet, which also makes it
+    /// known to them.
+    pub(crate) fn announce_to_tracker<R: Rng + ?Sized, Obs: RunObserver>(
+        &mut self,
+        peer_index: usize,
+        round_number: usize,
+        rng: &mut R,
+        observer: &Obs,
+    ) {
+        let Some(tracker) = self.tracker else {
+            return;
+        };
+        let candidates: Vec<usize> = self
+            .peers
+            .iter()
+            .filter(|p| p.is_active && p.index != peer_index)
+            .map(|p| p.index)
+            .collect();
+        let mut new_peers = 0;
+        for other in candidates.choose_multiple(rng, tracker.peer_list_size) {
+            if !self.peers[peer_index].is_neighbour(*other) {
+                self.connect(peer_index, *other);
+                new_peers += 1;
+            }
+        }
+        self.peers[peer_index].last_announce_round = Some(round_number);
+        observer.tracker_announce(peer_index, new_peers);
+    }
+
+    fn connect(&mut self, a: usize, b: usize) {
+        for (peer, other) in [(a, b), (b, a)] {
+            if let Some(neighbours) = &mut self.peers[peer].neighbours {
+                if let Err(index) = neighbours.binary_search(&other) {
+                    neighbours.insert(index, other);
+                }
+            }
+        }
+    }
+}